@@ -1,4 +1,3 @@
-
 def make_test(func):
     import sys
     import json
@@ -6,26 +5,128 @@ def make_test(func):
     for l in sys.stdin:
         program.append(l)
     ret = json.loads("\n".join(program).strip())
-    return ret["code"] + f"\nresult = {func}(*args)" , eval(ret["test_case"])
+    return ret["code"] + f"\nresult = {func}(*args)" , eval(ret["test_case"]), ret["nonce"]
+
+# Reports progress to the server on stderr, which strips these lines from error messages
+# The server only believes lines with the nonce it sent, which only ever lives in this process
+def progress(nonce, **event):
+    import sys
+    import json
+    print(f"#progress {nonce} " + json.dumps(event), file=sys.stderr, flush=True)
+
+def prctl(option, arg):
+    import ctypes
+    ctypes.CDLL(None).prctl(option, arg, 0, 0, 0)
 
-def test(program, test_case):
+# Runs the submission in a child process, and checks what it returns here,
+# so nothing the submission does can reach the nonce or the expected answers
+# The child only gets the code and the arguments for each test case
+def test(program, test_case, nonce, func):
+    import os
     import sys
-    for args, expect in test_case:
+    import json
+    import subprocess
+    from ast import literal_eval
+
+    # Keep the child from reading this process' memory through /proc or ptrace
+    prctl(PR_SET_DUMPABLE, 0)
+
+    results_r, results_w = os.pipe()
+    control_r, control_w = os.pipe()
+    child = subprocess.Popen(
+        [sys.executable, __file__, func, "--child", str(control_r), str(results_w)],
+        stdin=subprocess.DEVNULL, pass_fds=(control_r, results_w),
+    )
+    os.close(control_r)
+    os.close(results_w)
+    results = os.fdopen(results_r)
+    control = os.fdopen(control_w, "w")
+
+    def send(line):
+        try:
+            control.write(line + "\n")
+            control.flush()
+        except BrokenPipeError:
+            pass
+
+    # The child's exit code, or 128 + the signal that killed it, like a shell would report
+    def finish():
+        code = child.wait()
+        return 128 - code if code < 0 else code
+
+    send(json.dumps({"code": program, "args": [repr(args) for args, _ in test_case]}))
+    if results.readline() != "compiled\n":
+        sys.exit(finish())
+    progress(nonce, event="compiled")
+    # The child only runs a test when told to, so its output never lands in the middle of a progress line
+    send("next")
+
+    for i, (args, expect) in enumerate(test_case):
+        try:
+            result = json.loads(results.readline())
+        except ValueError:
+            # The child stopped before answering
+            sys.exit(finish())
+        try:
+            passed = literal_eval(result["repr"]) == expect
+        except (ValueError, SyntaxError, TypeError, MemoryError, RecursionError):
+            # Not a plain value, so compare how it prints
+            passed = result["repr"] == repr(expect)
+        progress(nonce, event="test", test=i + 1, total=len(test_case), passed=passed)
+        if not passed:
+            child.kill()
+            child.wait()
+            print(f"Test case failed on input `{args}`: Expected \n`{expect}`\nbut got \n`{result['str']}`", end='')
+            sys.exit(2)
+        send("next")
+
+    code = finish()
+    if code != 0:
+        sys.exit(code)
+    progress(nonce, event="done")
+    print("All test cases passed!", end='')
+
+# Runs in the child: compiles the submission, then sends back what it returns for each test case
+def run(control_fd, results_fd):
+    import os
+    import sys
+    import json
+    import signal
+
+    # Don't outlive the harness if it's killed for a timeout
+    prctl(PR_SET_PDEATHSIG, signal.SIGKILL)
+    control = os.fdopen(control_fd)
+    results = os.fdopen(results_fd, "w")
+
+    ret = json.loads(control.readline())
+    program = compile(ret["code"], "<string>", "exec")
+    results.write("compiled\n")
+    results.flush()
+
+    for args in ret["args"]:
+        if control.readline() != "next\n":
+            return
         # Use context with only arguements for locals
         # Also copies global context so it is not modified
-        local = {"args": args}
+        local = {"args": eval(args)}
         exec(program, globals().copy(), local)
-        if local["result"] != expect:
-            print(f"Test case failed on input `{args}`: Expected \n`{expect}`\nbut got \n`{local['result']}`", end='')
-            sys.exit(2)
+        # Anything the submission printed comes out before the harness' message
+        sys.stdout.flush()
+        results.write(json.dumps({"repr": repr(local["result"]), "str": str(local["result"])}) + "\n")
+        results.flush()
 
-    print("All test cases passed!", end='')
 
+PR_SET_PDEATHSIG = 1
+PR_SET_DUMPABLE = 4
 
 if __name__ == "__main__":
     from argparse import ArgumentParser
     parser = ArgumentParser()
     parser.add_argument("func", type=str)
+    parser.add_argument("--child", type=int, nargs=2)
     args = parser.parse_args()
-    program, test_case = make_test(args.func)
-    test(program, test_case)
+    if args.child:
+        run(*args.child)
+    else:
+        program, test_case, nonce = make_test(args.func)
+        test(program, test_case, nonce, args.func)
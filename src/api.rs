@@ -0,0 +1,329 @@
+use std::convert::Infallible;
+use serde::{Deserialize, Serialize};
+use warp::http::StatusCode;
+use crate::queue::JobId;
+use crate::config::{Question, Difficulty, Sample, Language, LANGUAGES, CONFIG};
+use crate::contest::{Status, Score};
+use crate::exec::docker::MEMORY_LIMIT_BYTES;
+use crate::db::{User, TokenInfo};
+use crate::test::Reply;
+
+// Machine readable reason for a failed request, sent in every error body
+#[derive(Serialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    InvalidEncoding,
+    NotFound,
+    Unauthorized,
+    Forbidden,
+    UsernameTaken,
+    UnknownQuestion,
+    UnknownLanguage,
+    UnknownContest,
+    HandleTaken,
+    InvalidQuestion,
+    QuestionInUse,
+    ContestNotOpen,
+    LanguageNotAllowed,
+    MethodNotAllowed,
+    PayloadTooLarge,
+    RateLimited,
+    QueueFull,
+    ShuttingDown,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::BadRequest | ErrorCode::InvalidEncoding | ErrorCode::InvalidQuestion => StatusCode::BAD_REQUEST,
+            ErrorCode::NotFound | ErrorCode::UnknownQuestion | ErrorCode::UnknownLanguage | ErrorCode::UnknownContest => StatusCode::NOT_FOUND,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden | ErrorCode::ContestNotOpen | ErrorCode::LanguageNotAllowed => StatusCode::FORBIDDEN,
+            ErrorCode::UsernameTaken | ErrorCode::HandleTaken | ErrorCode::QuestionInUse => StatusCode::CONFLICT,
+            ErrorCode::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::QueueFull | ErrorCode::ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+// Rejection that `handle_rejection` turns into an error body
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl warp::reject::Reject for ApiError {}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: &str) -> ApiError {
+        ApiError {code, message: message.to_string()}
+    }
+
+    pub fn reject(code: ErrorCode, message: &str) -> warp::Rejection {
+        warp::reject::custom(ApiError::new(code, message))
+    }
+}
+
+// Body of every error response
+#[derive(Serialize, Debug)]
+pub struct ErrorBody {
+    pub error: ApiError,
+}
+
+// Response to queueing a submission
+#[derive(Serialize, Debug)]
+pub struct Submitted {
+    pub id: JobId,
+}
+
+// Query string of running or submitting code
+#[derive(Deserialize, Debug, Default)]
+pub struct RunOptions {
+    // Judge the code again even if it was judged recently, only for admins
+    #[serde(default)]
+    pub fresh: bool,
+}
+
+// Query string of checking for copied submissions
+#[derive(Deserialize, Debug, Default)]
+pub struct SimilarityOptions {
+    // Only compare submissions made in this contest
+    pub contest: Option<String>,
+    // From 0 to 1
+    pub threshold: Option<f64>,
+}
+
+// Body of signing up and logging in
+#[derive(Deserialize, Debug)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Account<'a> {
+    #[serde(flatten)]
+    pub user: &'a User,
+    pub admin: bool,
+}
+
+impl<'a> From<&'a User> for Account<'a> {
+    fn from(user: &'a User) -> Account<'a> {
+        Account {user, admin: user.is_admin()}
+    }
+}
+
+// The token is also set as a cookie, but scripts can send it as a bearer token instead
+#[derive(Serialize, Debug)]
+pub struct LoggedIn<'a> {
+    pub token: &'a str,
+    pub user: Account<'a>,
+}
+
+// Body of making an api token
+#[derive(Deserialize, Debug)]
+pub struct NewToken {
+    pub name: String,
+}
+
+// This is the only time the token itself is shown
+#[derive(Serialize, Debug)]
+pub struct CreatedToken<'a> {
+    pub token: &'a str,
+    #[serde(flatten)]
+    pub info: TokenInfo,
+}
+
+// A contest as one participant sees it
+#[derive(Serialize, Debug)]
+pub struct ContestDetail<'a> {
+    pub handle: &'a str,
+    pub title: &'a str,
+    pub questions: &'a [String],
+    pub languages: &'a [Language],
+    pub start: i64,
+    pub end: i64,
+    pub duration: Option<u64>,
+    // The server's clock, so countdowns don't depend on the client's
+    pub now: i64,
+    pub status: Status,
+    pub score: Score,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Boilerplate<'a> {
+    pub handle: &'a str,
+    pub language: Language,
+    pub boilerplate: &'a str,
+}
+
+// A question in the list of all questions
+#[derive(Serialize, Debug)]
+pub struct QuestionSummary<'a> {
+    pub handle: &'a str,
+    pub title: &'a str,
+    pub difficulty: Option<Difficulty>,
+    pub languages: &'static [Language],
+    pub tags: &'a [String],
+}
+
+impl<'a> From<&'a Question> for QuestionSummary<'a> {
+    fn from(q: &'a Question) -> QuestionSummary<'a> {
+        QuestionSummary {
+            handle: &q.handle,
+            title: q.title(),
+            difficulty: q.difficulty,
+            languages: &LANGUAGES,
+            tags: &q.tags,
+        }
+    }
+}
+
+// A question on the index page, with how the user has done on it
+#[derive(Serialize, Debug)]
+pub struct QuestionStatus<'a> {
+    #[serde(flatten)]
+    pub summary: QuestionSummary<'a>,
+    pub attempted: bool,
+    pub solved: bool,
+}
+
+// Everything needed to show a question and start answering it
+#[derive(Serialize, Debug)]
+pub struct QuestionDetail<'a> {
+    #[serde(flatten)]
+    pub summary: QuestionSummary<'a>,
+    pub prompt: &'a str,
+    // The prompt rendered from markdown, safe to put straight into a page
+    pub prompt_html: String,
+    pub function_name: &'a str,
+    pub samples: &'a [Sample],
+    pub python: LanguageDetail<'a>,
+    pub java: LanguageDetail<'a>,
+    pub limits: Limits,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LanguageDetail<'a> {
+    pub signature: &'a str,
+    pub boilerplate: &'a str,
+    // Overrides `limits.time_ms` for this language
+    pub time_ms: u32,
+}
+
+// Limits every submission runs under
+#[derive(Serialize, Debug)]
+pub struct Limits {
+    pub time_ms: u32,
+    pub memory_bytes: u64,
+    pub output_bytes: usize,
+}
+
+impl<'a> From<&'a Question> for QuestionDetail<'a> {
+    fn from(q: &'a Question) -> QuestionDetail<'a> {
+        QuestionDetail {
+            summary: q.into(),
+            prompt: &q.prompt,
+            prompt_html: crate::markdown::render(&q.prompt, &q.handle),
+            function_name: &q.function_name,
+            samples: &q.samples,
+            python: LanguageDetail {
+                signature: &q.python.signature,
+                boilerplate: &q.python.boilerplate,
+                time_ms: q.time_limit(Language::Python),
+            },
+            java: LanguageDetail {
+                signature: &q.java.signature,
+                boilerplate: &q.java.boilerplate,
+                time_ms: q.time_limit(Language::Java),
+            },
+            limits: Limits {
+                time_ms: CONFIG.timeout,
+                memory_bytes: MEMORY_LIMIT_BYTES,
+                output_bytes: CONFIG.output_limit,
+            },
+        }
+    }
+}
+
+// Body of creating or editing a question
+// Enabling it publishes it, which only happens if its reference solutions pass
+#[derive(Deserialize, Debug)]
+pub struct QuestionEdit {
+    pub question: Question,
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+// How a reference solution did, None if there isn't one
+#[derive(Serialize, Debug)]
+pub struct SolutionRun {
+    pub language: Language,
+    pub reply: Option<Reply>,
+}
+
+// A question as it would be shown, without saving it
+#[derive(Serialize, Debug)]
+pub struct Preview<'a> {
+    pub question: QuestionDetail<'a>,
+    // Anything that would stop it from being published
+    pub problems: Vec<String>,
+    pub solutions: Vec<SolutionRun>,
+}
+
+// Render any rejection as an error body, so clients never get warp's plain text errors
+pub async fn handle_rejection(err: warp::Rejection) -> Result<impl warp::Reply, Infallible> {
+    let error = if let Some(e) = err.find::<ApiError>() {
+        e.clone()
+    } else if err.is_not_found() {
+        ApiError::new(ErrorCode::NotFound, "Not found.")
+    } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
+        ApiError::new(ErrorCode::MethodNotAllowed, "Method not allowed.")
+    } else if err.find::<warp::cors::CorsForbidden>().is_some() {
+        ApiError::new(ErrorCode::Forbidden, "Origin not allowed.")
+    } else if err.find::<warp::reject::PayloadTooLarge>().is_some() {
+        ApiError::new(ErrorCode::PayloadTooLarge, "Request body is too large.")
+    } else if err.find::<warp::reject::LengthRequired>().is_some()
+        || err.find::<warp::reject::InvalidQuery>().is_some()
+        || err.find::<warp::body::BodyDeserializeError>().is_some()
+        || err.find::<warp::reject::UnsupportedMediaType>().is_some()
+        || err.find::<warp::reject::InvalidHeader>().is_some()
+        || err.find::<warp::reject::MissingHeader>().is_some() {
+        ApiError::new(ErrorCode::BadRequest, "Bad request.")
+    } else {
+        ApiError::new(ErrorCode::Internal, "Internal server error.")
+    };
+
+    let status = error.code.status();
+    Ok(warp::reply::with_status(warp::reply::json(&ErrorBody {error}), status))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use warp::Reply;
+
+    #[tokio::test]
+    async fn test_handle_rejection() {
+        let res = handle_rejection(ApiError::reject(ErrorCode::RateLimited, "Slow down.")).await.unwrap().into_response();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let res = handle_rejection(warp::reject::not_found()).await.unwrap().into_response();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_error_body() {
+        let body = ErrorBody {error: ApiError::new(ErrorCode::UnknownQuestion, "No such question.")};
+        assert_eq!(
+            serde_json::to_string(&body).unwrap(),
+            r#"{"error":{"code":"unknown_question","message":"No such question."}}"#
+        );
+    }
+}
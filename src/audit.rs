@@ -0,0 +1,241 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use log::warn;
+use crate::config::Language;
+
+// Something someone did that we might have to answer for later
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    // `submission` is its id in the database, None if it couldn't be saved
+    // `fresh` is set when an admin skipped the verdict cache
+    Submitted { submission: Option<i64>, question: String, language: Language, contest: Option<String>, fresh: bool },
+    RateLimited { question: String, language: Language },
+    LoggedIn,
+    LoginFailed,
+    // A security check that should have failed in the sandbox didn't
+    SandboxEscape { check: String },
+    // Hash of the config file the server started with
+    ConfigLoaded { sha256: String },
+    // Anything admins can do that others can't
+    Admin { did: String },
+}
+
+impl Action {
+    // Ties what happened afterwards to the exact config it happened under
+    pub fn config_loaded(config: &str) -> Action {
+        Action::ConfigLoaded {sha256: crate::auth::hex(&Sha256::digest(config.as_bytes()))}
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Submitted {..} => "submitted",
+            Action::RateLimited {..} => "rate_limited",
+            Action::LoggedIn => "logged_in",
+            Action::LoginFailed => "login_failed",
+            Action::SandboxEscape {..} => "sandbox_escape",
+            Action::ConfigLoaded {..} => "config_loaded",
+            Action::Admin {..} => "admin",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct Event {
+    // Seconds since the epoch
+    pub at: i64,
+    // Username, None for the server itself
+    pub user: Option<String>,
+    // Where the request came from, if it came from anywhere
+    pub ip: Option<String>,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+// What to look for in the log, everything if it's all None
+#[derive(Deserialize, Debug, Default)]
+pub struct Query {
+    pub user: Option<String>,
+    pub action: Option<String>,
+    pub since: Option<i64>,
+    pub limit: Option<usize>,
+}
+
+impl Query {
+    fn matches(&self, event: &Event) -> bool {
+        self.user.as_ref().is_none_or(|u| event.user.as_ref() == Some(u))
+            && self.action.as_ref().is_none_or(|a| a == event.action.name())
+            && self.since.is_none_or(|s| event.at >= s)
+    }
+}
+
+// Events given back by a query if it doesn't say
+const DEFAULT_LIMIT: usize = 100;
+
+enum Message {
+    Record(String),
+    // Answered once everything sent before it is written
+    Flush(mpsc::Sender<()>),
+}
+
+// JSON lines, only ever appended to
+// Once the file reaches `max_bytes` it's moved to `<path>.1`, pushing older ones up to `<path>.<keep>`
+// Files are written on a thread of their own, so recording never waits on the disk
+pub struct Audit {
+    path: PathBuf,
+    keep: usize,
+    writer: mpsc::Sender<Message>,
+}
+
+fn open(path: &Path) -> Result<File> {
+    Ok(OpenOptions::new().create(true).append(true).open(path)?)
+}
+
+// `<path>.n`, or `path` itself for 0
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    if n == 0 {
+        path.to_path_buf()
+    } else {
+        let mut name = path.to_path_buf().into_os_string();
+        name.push(format!(".{}", n));
+        name.into()
+    }
+}
+
+fn write(file: &mut File, path: &Path, line: &str, max_bytes: u64, keep: usize) -> Result<()> {
+    writeln!(file, "{}", line)?;
+    file.flush()?;
+
+    if file.metadata()?.len() >= max_bytes {
+        // Whatever was in the last one falls off the end
+        for n in (0..keep).rev() {
+            let from = rotated(path, n);
+            if from.exists() {
+                std::fs::rename(from, rotated(path, n + 1))?;
+            }
+        }
+        *file = open(path)?;
+    }
+    Ok(())
+}
+
+impl Audit {
+    pub fn open(path: impl Into<PathBuf>, max_bytes: u64, keep: usize) -> Result<Audit> {
+        let path = path.into();
+        let mut file = open(&path)?;
+        let (writer, messages) = mpsc::channel();
+
+        let write_path = path.clone();
+        std::thread::Builder::new().name("audit".to_string()).spawn(move || {
+            for message in messages {
+                match message {
+                    Message::Record(line) => if let Err(e) = write(&mut file, &write_path, &line, max_bytes, keep) {
+                        warn!("Failed to write to the audit log: {}", e);
+                    },
+                    Message::Flush(done) => { done.send(()).ok(); },
+                }
+            }
+        })?;
+
+        Ok(Audit {path, keep, writer})
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        rotated(&self.path, n)
+    }
+
+    // Queues the event to be written
+    pub fn record(&self, event: &Event) -> Result<()> {
+        let line = serde_json::to_string(event)?;
+        self.writer.send(Message::Record(line)).map_err(|_| anyhow::anyhow!("The audit log writer has stopped"))
+    }
+
+    // Waits for everything recorded so far to be written
+    pub fn flush(&self) {
+        let (done, wait) = mpsc::channel();
+        if self.writer.send(Message::Flush(done)).is_ok() {
+            wait.recv().ok();
+        }
+    }
+
+    // Newest first
+    // Doesn't hold up writing, so events rotated while it reads can be missed or seen twice
+    pub fn query(&self, query: &Query) -> Result<Vec<Event>> {
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+        let mut events = Vec::new();
+
+        for n in 0..=self.keep {
+            let path = self.rotated(n);
+            if !path.exists() {
+                break
+            }
+            let lines = BufReader::new(File::open(path)?).lines().collect::<std::io::Result<Vec<_>>>()?;
+            for line in lines.iter().rev() {
+                // Lines cut short by a crash are skipped
+                if let Ok(event) = serde_json::from_str::<Event>(line) {
+                    if query.matches(&event) {
+                        events.push(event);
+                        if events.len() >= limit {
+                            return Ok(events)
+                        }
+                    }
+                }
+            }
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event(at: i64, user: &str, action: Action) -> Event {
+        Event {at, user: Some(user.to_string()), ip: Some("127.0.0.1".to_string()), action}
+    }
+
+    #[test]
+    fn test_audit() {
+        let path = std::env::temp_dir().join(format!("code-quiz-audit-test-{}.log", std::process::id()));
+        // Small enough that every few events rotate
+        let audit = Audit::open(&path, 200, 2).unwrap();
+
+        let submitted = |at| event(at, "alice", Action::Submitted {
+            submission: Some(at), question: "identity".to_string(), language: Language::Python, contest: None, fresh: false,
+        });
+        for at in 0..10 {
+            audit.record(&submitted(at)).unwrap();
+        }
+        audit.record(&event(10, "bob", Action::LoginFailed)).unwrap();
+        audit.flush();
+
+        let all = audit.query(&Query::default()).unwrap();
+        assert_eq!(all[0], event(10, "bob", Action::LoginFailed));
+        assert_eq!(all[1], submitted(9));
+        // Older events were rotated away
+        assert!(all.len() < 11);
+        assert!(all.windows(2).all(|w| w[0].at > w[1].at));
+
+        let query = Query {user: Some("alice".to_string()), action: Some("submitted".to_string()), since: Some(8), limit: None};
+        assert_eq!(audit.query(&query).unwrap(), vec![submitted(9), submitted(8)]);
+        let query = Query {limit: Some(1), ..Query::default()};
+        assert_eq!(audit.query(&query).unwrap().len(), 1);
+
+        for n in 0..=3 {
+            std::fs::remove_file(audit.rotated(n)).ok();
+        }
+    }
+
+    #[test]
+    fn test_serialize() {
+        let line = serde_json::to_value(event(1, "admin", Action::Admin {did: "viewed the audit log".to_string()})).unwrap();
+        assert_eq!(line, serde_json::json!({
+            "at": 1, "user": "admin", "ip": "127.0.0.1", "action": "admin", "did": "viewed the audit log",
+        }));
+    }
+}
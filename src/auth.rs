@@ -0,0 +1,126 @@
+use anyhow::Result;
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+
+// Cookie a browser login is kept in
+pub const SESSION_COOKIE: &str = "session";
+
+// Tokens start with this, so they're easy to spot if they end up somewhere they shouldn't
+const TOKEN_PREFIX: &str = "cq_";
+
+const MIN_PASSWORD_LEN: usize = 8;
+const MAX_PASSWORD_LEN: usize = 256;
+const MAX_USERNAME_LEN: usize = 32;
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt: [u8; 16] = rand::random();
+    let config = argon2::Config {variant: argon2::Variant::Argon2id, ..argon2::Config::default()};
+    Ok(argon2::hash_encoded(password.as_bytes(), &salt, &config)?)
+}
+
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    argon2::verify_encoded(hash, password.as_bytes()).unwrap_or(false)
+}
+
+lazy_static! {
+    // Checked instead when there's no such user, so logging in as anyone takes as long
+    pub static ref DUMMY_HASH: String = hash_password("").expect("Failed to hash a password");
+}
+
+// Make a new random token, returning it and the hash to store
+pub fn new_token() -> (String, String) {
+    let bytes: [u8; 32] = rand::random();
+    let token = format!("{}{}", TOKEN_PREFIX, hex(&bytes));
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+// Tokens are already random, so a fast hash is enough
+pub fn hash_token(token: &str) -> String {
+    hex(&Sha256::digest(token.as_bytes()))
+}
+
+// Returns why the username can't be used, if it can't
+pub fn check_username(username: &str) -> Option<&'static str> {
+    if username.is_empty() || username.len() > MAX_USERNAME_LEN {
+        Some("Usernames must be between 1 and 32 characters.")
+    } else if !username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        Some("Usernames can only have letters, numbers, underscores and dashes.")
+    } else {
+        None
+    }
+}
+
+// Returns why the password can't be used, if it can't
+pub fn check_password(password: &str) -> Option<&'static str> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        Some("Passwords must be at least 8 characters.")
+    } else if password.len() > MAX_PASSWORD_LEN {
+        Some("Passwords must be at most 256 bytes.")
+    } else {
+        None
+    }
+}
+
+// Pick a token out of an `Authorization: Bearer` header, falling back to the session cookie
+pub fn find_token(authorization: Option<&str>, cookie: Option<&str>) -> Option<String> {
+    match authorization {
+        Some(header) => header.strip_prefix("Bearer ").map(|t| t.trim().to_string()),
+        None => cookie.map(|c| c.to_string()),
+    }
+}
+
+// Set-Cookie value for a login lasting `max_age` seconds
+// A max age of 0 clears the cookie
+// Browsers only send `secure` cookies over https, and to localhost
+pub fn session_cookie(token: &str, max_age: u64, secure: bool) -> String {
+    let secure = if secure { "; Secure" } else { "" };
+    format!("{}={}; Max-Age={}; Path=/; HttpOnly; SameSite=Strict{}", SESSION_COOKIE, token, max_age, secure)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_password() {
+        let hash = hash_password("hunter22").unwrap();
+        assert!(!hash.contains("hunter22"));
+        assert!(verify_password(&hash, "hunter22"));
+        assert!(!verify_password(&hash, "hunter23"));
+        assert!(!verify_password("not a hash", "hunter22"));
+        assert!(!verify_password(&DUMMY_HASH, "hunter22"));
+
+        // Salted
+        assert_ne!(hash, hash_password("hunter22").unwrap());
+    }
+
+    #[test]
+    fn test_token() {
+        let (token, hash) = new_token();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(hash, hash_token(&token));
+        assert_ne!(token, new_token().0);
+
+        assert_eq!(find_token(Some("Bearer abc"), Some("def")), Some("abc".to_string()));
+        assert_eq!(find_token(Some("Basic abc"), Some("def")), None);
+        assert_eq!(find_token(None, Some("def")), Some("def".to_string()));
+        assert_eq!(find_token(None, None), None);
+
+        assert_eq!(session_cookie("abc", 60, false), "session=abc; Max-Age=60; Path=/; HttpOnly; SameSite=Strict");
+        assert_eq!(session_cookie("abc", 60, true), "session=abc; Max-Age=60; Path=/; HttpOnly; SameSite=Strict; Secure");
+    }
+
+    #[test]
+    fn test_check() {
+        assert_eq!(check_username("alice_1"), None);
+        assert!(check_username("").is_some());
+        assert!(check_username("alice smith").is_some());
+        assert_eq!(check_password("hunter22"), None);
+        assert!(check_password("short").is_some());
+    }
+}
@@ -0,0 +1,121 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use sha2::{Digest, Sha256};
+use crate::config::Language;
+use crate::db::TestResult;
+use crate::test::{Reply, Verdict};
+
+// When it was judged, the question it was for, and how it went
+type Cached = (Instant, String, Reply, Vec<TestResult>);
+
+// Verdicts for code that was judged recently, so resubmitting it doesn't start another container
+pub struct ResultCache {
+    results: Mutex<lru::LruCache<Key, Cached>>,
+    ttl: Duration,
+    enabled: bool,
+}
+
+// Line endings and a trailing newline can't change what the code does
+// Anything else can, like whitespace inside a python triple-quoted string or a java text block
+pub fn normalize(code: &str) -> String {
+    let code = code.replace("\r\n", "\n");
+    code.strip_suffix('\n').unwrap_or(&code).to_string()
+}
+
+// What a submission is cached under, a hash of its question, language and normalized code
+pub type Key = [u8; 32];
+
+pub fn key(question: &str, language: Language, code: &str) -> Key {
+    let mut hasher = Sha256::new();
+    for part in &[question, language.name(), &normalize(code)] {
+        hasher.update(part.as_bytes());
+        // Keeps ("ab", "c") and ("a", "bc") apart
+        hasher.update(b"\0");
+    }
+    hasher.finalize().into()
+}
+
+impl ResultCache {
+    pub fn new(capacity: usize, ttl: Duration) -> ResultCache {
+        ResultCache {
+            results: Mutex::new(lru::LruCache::new(capacity.max(1))),
+            ttl,
+            enabled: capacity > 0,
+        }
+    }
+
+    pub fn get(&self, key: &Key) -> Option<(Reply, Vec<TestResult>)> {
+        let mut results = self.results.lock().unwrap();
+        match results.get(key) {
+            Some((at, _, reply, tests)) if at.elapsed() < self.ttl => Some((reply.clone(), tests.clone())),
+            Some(_) => {
+                results.pop(key);
+                None
+            },
+            None => None,
+        }
+    }
+
+    // Internal errors are the server's fault, so they aren't kept
+    pub fn put(&self, key: Key, question: &str, reply: &Reply, tests: &[TestResult]) {
+        if !self.enabled || reply.verdict == Verdict::InternalError {
+            return
+        }
+        self.results.lock().unwrap().put(key, (Instant::now(), question.to_string(), reply.clone(), tests.to_vec()));
+    }
+
+    // For when a question's tests change, and every verdict for it could be wrong
+    pub fn forget(&self, question: &str) {
+        let mut results = self.results.lock().unwrap();
+        let keys: Vec<Key> = results.iter().filter(|(_, c)| c.1 == question).map(|(k, _)| *k).collect();
+        for key in keys {
+            results.pop(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        let code = "def f(x):\n    return x\n";
+        assert_eq!(key("q", Language::Python, code), key("q", Language::Python, "def f(x):\r\n    return x"));
+        // Both could be inside a string
+        assert_ne!(key("q", Language::Python, code), key("q", Language::Python, "def f(x):   \n    return x\n"));
+        assert_ne!(key("q", Language::Python, code), key("q", Language::Python, "def f(x):\n\n    return x\n"));
+        assert_ne!(key("q", Language::Python, code), key("q", Language::Python, "def f(x):\n  return x"));
+        assert_ne!(key("q", Language::Python, code), key("q", Language::Java, code));
+        assert_ne!(key("q", Language::Python, code), key("p", Language::Python, code));
+    }
+
+    #[test]
+    fn test_cache() {
+        let tests = [TestResult {test: 1, total: 1, passed: true}];
+        let accepted = Reply::new(Verdict::Accepted, "", None);
+
+        let a = key("a", Language::Python, "");
+        let b = key("b", Language::Python, "");
+
+        let cache = ResultCache::new(10, Duration::from_secs(60));
+        assert_eq!(cache.get(&a), None);
+        cache.put(a, "a", &accepted, &tests);
+        assert_eq!(cache.get(&a), Some((accepted.clone(), tests.to_vec())));
+        cache.put(b, "b", &Reply::internal_error(), &[]);
+        assert_eq!(cache.get(&b), None);
+
+        cache.put(b, "b", &accepted, &tests);
+        cache.forget("a");
+        assert_eq!(cache.get(&a), None);
+        assert!(cache.get(&b).is_some());
+
+        let expired = ResultCache::new(10, Duration::from_secs(0));
+        expired.put(a, "a", &accepted, &tests);
+        assert_eq!(expired.get(&a), None);
+
+        let disabled = ResultCache::new(0, Duration::from_secs(60));
+        disabled.put(a, "a", &accepted, &tests);
+        assert_eq!(disabled.get(&a), None);
+    }
+}
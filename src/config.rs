@@ -1,6 +1,51 @@
 use serde::{Deserialize, Serialize};
 use lazy_static::lazy_static;
 use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use chrono::{DateTime, Utc};
+use crate::scoreboard::Scoring;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Language {
+    Python,
+    Java,
+}
+
+// Languages every question can be answered in
+pub const LANGUAGES: [Language; 2] = [Language::Python, Language::Java];
+
+impl Language {
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Python => "python",
+            Language::Java => "java",
+        }
+    }
+
+    // Docker image submissions are run in
+    pub fn image(self) -> &'static str {
+        match self {
+            Language::Python | Language::Java => "test",
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Language {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Language, Self::Err> {
+        LANGUAGES.iter().copied().find(|l| l.name() == s)
+            .ok_or_else(|| anyhow::Error::msg(format!("Unsupported language {}", s)))
+    }
+}
 
 // TODO: Mock out the entire config for tests
 // TODO: Preserve order in parsing config
@@ -13,10 +58,29 @@ lazy_static!{
         let file_string = read_to_string("./config.toml")
             .expect("Failed to read toml");
         let mut timeout = 2000;
-        let mut template: String = String::new();
+        let mut index_template = String::new();
+        let mut question_template = String::new();
         let mut questions: HashMap<String, Question> = HashMap::new();
         let mut max_proc = 5;
         let mut java_test_file = String::new();
+        let mut shutdown_timeout = 10000;
+        let mut output_limit = 64 * 1024;
+        let mut queue_size = 100;
+        let mut readiness_ttl = 10;
+        let mut similarity_interval = 3600;
+        let mut cors = Cors::default();
+        let mut auth = Auth::default();
+        let mut cache = Cache::default();
+        let mut logging = Logging::default();
+        let mut audit = Audit::default();
+        let mut database = "code-quiz.db".to_string();
+        let mut contests: HashMap<String, Contest> = HashMap::new();
+        let mut scoring = Scoring::default();
+        let mut scoreboard_template = String::new();
+        let mut admin_template = String::new();
+        let mut question_assets = "questions".to_string();
+        let mut static_dir = "static".to_string();
+        let mut api_url = "/api/v1".to_string();
         match toml::from_str(&file_string).expect("work") {
             toml::Value::Table(t) => {
                 for (k, v) in t {
@@ -27,12 +91,72 @@ lazy_static!{
                         ("max_proc", Value::Integer(i)) => {
                             max_proc = *i as usize;
                         },
-                        ("template", Value::String(s)) => {
-                            template = s.to_owned();
+                        ("shutdown_timeout", Value::Integer(i)) => {
+                            shutdown_timeout = *i as u32;
+                        },
+                        ("output_limit", Value::Integer(i)) => {
+                            output_limit = *i as usize;
+                        },
+                        ("queue_size", Value::Integer(i)) => {
+                            queue_size = *i as usize;
+                        },
+                        ("readiness_ttl", Value::Integer(i)) => {
+                            readiness_ttl = *i as u64;
+                        },
+                        ("similarity_interval", Value::Integer(i)) => {
+                            similarity_interval = *i as u64;
+                        },
+                        ("index_template", Value::String(s)) => {
+                            index_template = s.to_owned();
+                        },
+                        ("question_template", Value::String(s)) => {
+                            question_template = s.to_owned();
+                        },
+                        ("scoring", Value::String(_)) => {
+                            scoring = v.clone().try_into().expect("Invalid scoring");
+                        },
+                        ("scoreboard_template", Value::String(s)) => {
+                            scoreboard_template = s.to_owned();
+                        },
+                        ("admin_template", Value::String(s)) => {
+                            admin_template = s.to_owned();
+                        },
+                        ("question_assets", Value::String(s)) => {
+                            question_assets = s.to_owned();
+                        },
+                        ("static_dir", Value::String(s)) => {
+                            static_dir = s.to_owned();
+                        },
+                        ("api_url", Value::String(s)) => {
+                            api_url = s.trim_end_matches('/').to_owned();
+                        },
+                        ("database", Value::String(s)) => {
+                            database = s.to_owned();
                         },
                         ("java_test_file", Value::String(s)) => {
                             java_test_file = read_to_string(s).unwrap();
                         },
+                        ("cors", Value::Table(_)) => {
+                            cors = v.clone().try_into().expect("Invalid cors config");
+                        },
+                        ("contests", Value::Table(tables)) => {
+                            for v in tables.values() {
+                                let c: Contest = v.clone().try_into().expect("Invalid contest config");
+                                contests.insert(c.handle.to_string(), c);
+                            }
+                        },
+                        ("auth", Value::Table(_)) => {
+                            auth = v.clone().try_into().expect("Invalid auth config");
+                        },
+                        ("logging", Value::Table(_)) => {
+                            logging = v.clone().try_into().expect("Invalid logging config");
+                        },
+                        ("audit", Value::Table(_)) => {
+                            audit = v.clone().try_into().expect("Invalid audit config");
+                        },
+                        ("cache", Value::Table(_)) => {
+                            cache = v.clone().try_into().expect("Invalid cache config");
+                        },
                         (_, Value::Table(_)) =>  {
                             let q: Question = v.try_into().unwrap();
                             questions.insert(q.handle.to_string(), q);
@@ -44,38 +168,434 @@ lazy_static!{
             },
             _ => panic!("what"),
         }
-        Config {timeout, template, questions, max_proc, java_test_file}
+        // Logins are a SameSite=Strict cookie, which pages on another origin wouldn't send
+        assert!(is_path(&api_url), "api_url has to be a path on the same host, like /api/v1");
+        for c in contests.values() {
+            assert!(c.start < c.end, "Contest {} ends before it starts", c.handle);
+            for q in &c.questions {
+                assert!(questions.contains_key(q), "Contest {} has unknown question {}", c.handle, q);
+            }
+        }
+        Config {timeout, index_template, question_template, questions, max_proc, java_test_file, shutdown_timeout, output_limit, queue_size, readiness_ttl, similarity_interval, cors, auth, cache, logging, audit, database, contests, scoring, scoreboard_template, admin_template, question_assets, static_dir, api_url}
     };
 }
 
 pub struct Config {
     pub timeout: u32,
-    pub template: String,
+    // Page listing every question
+    pub index_template: String,
+    // Page for answering one question
+    pub question_template: String,
     pub java_test_file: String,
     pub questions: HashMap<String, Question>,
     pub max_proc: usize,
+    // How long to wait for running submissions to finish on shutdown
+    pub shutdown_timeout: u32,
+    // Max bytes of stdout or stderr kept from a single execution
+    pub output_limit: usize,
+    // Max submissions waiting for a free container slot
+    pub queue_size: usize,
+    // Seconds a readiness check is reused for
+    pub readiness_ttl: u64,
+    // Seconds between batch similarity checks, which never run on their own if 0
+    pub similarity_interval: u64,
+    pub cors: Cors,
+    pub auth: Auth,
+    pub cache: Cache,
+    pub logging: Logging,
+    pub audit: Audit,
+    // Path to the sqlite database
+    pub database: String,
+    pub contests: HashMap<String, Contest>,
+    // How the scoreboard across every submission is ranked
+    pub scoring: Scoring,
+    pub scoreboard_template: String,
+    // Page for managing questions
+    pub admin_template: String,
+    // Images and other files prompts link to, in a directory per question handle
+    pub question_assets: String,
+    // Scripts and other files pages load from /static
+    pub static_dir: String,
+    // Where pages send api requests, like when it's proxied under another path
+    // Always on the same host as the pages, so the session cookie goes along
+    pub api_url: String,
+}
+
+// An absolute path, without a scheme or host
+fn is_path(url: &str) -> bool {
+    url.starts_with('/') && !url.starts_with("//")
+}
+
+// A timed set of questions
+// Submissions made through a contest only count if they're made while it's open
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct Contest {
+    pub handle: String,
+    // Defaults to the handle
+    #[serde(default)]
+    pub title: String,
+    // Question handles, in the order they're shown
+    pub questions: Vec<String>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    // Seconds each participant gets from when they start, if it isn't just start to end
+    #[serde(default)]
+    pub duration: Option<u64>,
+    #[serde(default = "Contest::default_languages")]
+    pub languages: Vec<Language>,
+    #[serde(default)]
+    pub scoring: Scoring,
+    // Only admins can see the scoreboard if this is false
+    #[serde(default = "Contest::default_public_scoreboard")]
+    pub public_scoreboard: bool,
+}
+
+impl Contest {
+    fn default_languages() -> Vec<Language> {
+        LANGUAGES.to_vec()
+    }
+
+    fn default_public_scoreboard() -> bool {
+        true
+    }
+
+    pub fn title(&self) -> &str {
+        if self.title.is_empty() { &self.handle } else { &self.title }
+    }
+}
+
+#[derive(Deserialize, Debug, Eq, PartialEq)]
+pub struct Auth {
+    // Seconds a login lasts for
+    #[serde(default = "Auth::default_session_ttl")]
+    pub session_ttl: u64,
+    // Whether anyone can make an account
+    #[serde(default = "Auth::default_signup")]
+    pub signup: bool,
+    // Accounts made admins on start, whose usernames nobody can sign up with
+    #[serde(default)]
+    pub admins: Vec<String>,
+    // Whether the session cookie is only sent over https
+    #[serde(default = "Auth::default_secure_cookie")]
+    pub secure_cookie: bool,
+}
+
+impl Auth {
+    fn default_session_ttl() -> u64 {
+        7 * 24 * 60 * 60
+    }
+
+    fn default_signup() -> bool {
+        false
+    }
+
+    fn default_secure_cookie() -> bool {
+        true
+    }
+}
+
+impl Default for Auth {
+    fn default() -> Auth {
+        Auth {
+            session_ttl: Auth::default_session_ttl(),
+            signup: Auth::default_signup(),
+            admins: Vec::new(),
+            secure_cookie: Auth::default_secure_cookie(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    // One JSON object per line
+    Json,
+    // Colored, for reading in a terminal
+    Pretty,
+}
+
+#[derive(Deserialize, Debug, Eq, PartialEq)]
+pub struct Logging {
+    #[serde(default = "Logging::default_format")]
+    pub format: LogFormat,
+    // Log how long submitted code is instead of the code itself
+    #[serde(default = "Logging::default_redact_code")]
+    pub redact_code: bool,
+}
+
+impl Logging {
+    fn default_format() -> LogFormat {
+        LogFormat::Json
+    }
+
+    fn default_redact_code() -> bool {
+        true
+    }
+}
+
+impl Default for Logging {
+    fn default() -> Logging {
+        Logging {
+            format: Logging::default_format(),
+            redact_code: Logging::default_redact_code(),
+        }
+    }
+}
+
+// Where the audit log is kept
+#[derive(Deserialize, Debug, Eq, PartialEq)]
+pub struct Audit {
+    #[serde(default = "Audit::default_path")]
+    pub path: String,
+    // Size the log is rotated at
+    #[serde(default = "Audit::default_max_bytes")]
+    pub max_bytes: u64,
+    // Rotated logs kept around
+    #[serde(default = "Audit::default_keep")]
+    pub keep: usize,
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Default)]
+impl Audit {
+    fn default_path() -> String {
+        "audit.log".to_string()
+    }
+
+    fn default_max_bytes() -> u64 {
+        10 * 1024 * 1024
+    }
+
+    fn default_keep() -> usize {
+        5
+    }
+}
+
+impl Default for Audit {
+    fn default() -> Audit {
+        Audit {
+            path: Audit::default_path(),
+            max_bytes: Audit::default_max_bytes(),
+            keep: Audit::default_keep(),
+        }
+    }
+}
+
+// Verdicts kept for code that's already been judged
+#[derive(Deserialize, Debug, Eq, PartialEq)]
+pub struct Cache {
+    // Max verdicts kept, 0 turns caching off
+    #[serde(default = "Cache::default_capacity")]
+    pub capacity: usize,
+    // Seconds a verdict is reused for
+    #[serde(default = "Cache::default_ttl")]
+    pub ttl: u64,
+}
+
+impl Cache {
+    fn default_capacity() -> usize {
+        1000
+    }
+
+    fn default_ttl() -> u64 {
+        10 * 60
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Cache {
+        Cache {
+            capacity: Cache::default_capacity(),
+            ttl: Cache::default_ttl(),
+        }
+    }
+}
+
+// Who else can call the api from a browser
+#[derive(Deserialize, Debug, Eq, PartialEq)]
+pub struct Cors {
+    // "*" allows any origin
+    // Requests without an origin, like from curl, are always allowed
+    #[serde(default)]
+    pub origins: Vec<String>,
+    #[serde(default = "Cors::default_methods")]
+    pub methods: Vec<String>,
+    #[serde(default = "Cors::default_headers")]
+    pub headers: Vec<String>,
+    // Seconds browsers can cache a preflight response for
+    #[serde(default)]
+    pub max_age: Option<u32>,
+}
+
+impl Cors {
+    fn default_methods() -> Vec<String> {
+        vec!["GET".to_string(), "POST".to_string(), "DELETE".to_string()]
+    }
+
+    fn default_headers() -> Vec<String> {
+        vec!["content-type".to_string(), "authorization".to_string()]
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Cors {
+        Cors {
+            origins: Vec::new(),
+            methods: Cors::default_methods(),
+            headers: Cors::default_headers(),
+            max_age: None,
+        }
+    }
+}
+
+// Questions here can be edited, disabled and added to through the admin api, see `questions::Questions`
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Default)]
 pub struct Question {
     pub handle: String,
     pub function_name: String,
+    // Markdown, with $math$ and images from `question_assets`
     pub prompt: String,
+    // Defaults to the handle
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub difficulty: Option<Difficulty>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // Example tests shown with the prompt, these aren't run
+    #[serde(default)]
+    pub samples: Vec<Sample>,
     pub python: Python,
     pub java: Java,
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Default)]
+impl Question {
+    pub fn title(&self) -> &str {
+        if self.title.is_empty() { &self.handle } else { &self.title }
+    }
+
+    pub fn boilerplate(&self, language: Language) -> &str {
+        match language {
+            Language::Python => &self.python.boilerplate,
+            Language::Java => &self.java.boilerplate,
+        }
+    }
+
+    // Milliseconds a submission in `language` gets, `timeout` unless the question says otherwise
+    pub fn time_limit(&self, language: Language) -> u32 {
+        let limit = match language {
+            Language::Python => self.python.time_limit,
+            Language::Java => self.java.time_limit,
+        };
+        limit.unwrap_or(CONFIG.timeout)
+    }
+
+    // Code that should pass every test, empty if there isn't any
+    pub fn solution(&self, language: Language) -> &str {
+        match language {
+            Language::Python => &self.python.solution,
+            Language::Java => &self.java.solution,
+        }
+    }
+
+    // Tests in `test_case`, counted from the items in its outermost list: `[...]` in python, `{...}` in java
+    pub fn test_count(&self, language: Language) -> usize {
+        match language {
+            Language::Python => count_items(&self.python.test_case, '['),
+            Language::Java => count_items(&self.java.test_case, '{'),
+        }
+    }
+}
+
+// Top level items in the first list opened with `open`, skipping over anything quoted
+// 0 if there's no such list
+fn count_items(source: &str, open: char) -> usize {
+    let mut depth = 0;
+    let mut started = false;
+    // Whether the current item has anything in it, so trailing commas don't count
+    let mut item = false;
+    let mut items = 0;
+    let mut quote = None;
+    let mut escaped = false;
+
+    for c in source.chars() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '[' | '(' | '{' => {
+                depth += 1;
+                if depth == 1 && c == open && !started {
+                    started = true;
+                    continue
+                }
+            },
+            ']' | ')' | '}' => {
+                depth -= 1;
+                if started && depth == 0 {
+                    return items + item as usize
+                }
+            },
+            ',' if started && depth == 1 => {
+                items += item as usize;
+                item = false;
+                continue
+            },
+            c if c.is_whitespace() => continue,
+            _ => {},
+        }
+        if started {
+            item = true;
+        }
+    }
+    0
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Default)]
+pub struct Sample {
+    pub input: String,
+    pub output: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Default)]
 pub struct Python {
     pub test_case: String,
     pub boilerplate: String,
+    #[serde(default)]
+    pub signature: String,
+    // Milliseconds, instead of `timeout`
+    #[serde(default)]
+    pub time_limit: Option<u32>,
+    // Reference solution, run before the question can be published through the admin api
+    #[serde(default)]
+    pub solution: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Default)]
 pub struct Java {
     pub test_case: String,
     pub boilerplate: String,
     pub func_call: String,
+    #[serde(default)]
+    pub signature: String,
+    #[serde(default)]
+    pub time_limit: Option<u32>,
+    #[serde(default)]
+    pub solution: String,
 }
 
 // Force evaluate lazy static
@@ -97,6 +617,7 @@ mod test {
             prompt: String::new(),
             python: Python::default(),
             java: Java::default(),
+            ..Question::default()
         };
         let questions = &CONFIG.questions;
         assert_eq!(questions.get("identity").unwrap().function_name, sample.function_name);
@@ -107,8 +628,46 @@ mod test {
         let sample_python = Python {
             test_case: "[([1], 1), ([2], 2), ([3], 3), ([-400], -400)]".to_string(),
             boilerplate: "def identity(x):\n    pass".to_string(),
+            signature: "def identity(x: int) -> int".to_string(),
+            ..Python::default()
         };
 
         assert_eq!(CONFIG.questions.get("identity").unwrap().python, sample_python);
     }
+
+    #[test]
+    fn test_parse_cors() {
+        let cors: Cors = toml::from_str("origins = [\"https://example.com\"]").unwrap();
+        assert_eq!(cors, Cors {origins: vec!["https://example.com".to_string()], ..Cors::default()});
+
+        assert_eq!(CONFIG.cors.methods, vec!["GET", "POST", "DELETE"]);
+        assert!(CONFIG.cors.origins.contains(&"http://localhost:8080".to_string()));
+    }
+
+    #[test]
+    fn test_count_items() {
+        assert_eq!(count_items("[([1], 1), ([2], 2), ([3], 3), ([-400], -400)]", '['), 4);
+        assert_eq!(count_items("[(['a,]', 'b\\'),'], 1),]", '['), 1);
+        assert_eq!(count_items("[]", '['), 0);
+        assert_eq!(count_items("", '['), 0);
+        let java = "TestCase<int[], Integer> testCase[] = new TestCase[] {new TestCase(new int[]{1}, 1), new TestCase(new int[]{2, 3}, 2)}";
+        assert_eq!(count_items(java, '{'), 2);
+    }
+
+    #[test]
+    fn test_api_url() {
+        assert_eq!(CONFIG.api_url, "/api/v1");
+        assert!(is_path("/quiz/api/v1"));
+        assert!(!is_path("https://api.example.com/api/v1"));
+        assert!(!is_path("//api.example.com/api/v1"));
+        assert!(!is_path("api/v1"));
+    }
+
+    #[test]
+    fn test_parse_language() {
+        assert_eq!("python".parse::<Language>().unwrap(), Language::Python);
+        assert_eq!("java".parse::<Language>().unwrap(), Language::Java);
+        assert!("cobol".parse::<Language>().is_err());
+        assert_eq!(Language::Java.to_string(), "java");
+    }
 }
@@ -0,0 +1,133 @@
+use serde::Serialize;
+use crate::config::Contest;
+use crate::test::Verdict;
+use crate::scoreboard::attempts;
+
+// Where a participant is in a contest
+// Times are seconds since the epoch, so clients can count down from the server's `now`
+#[derive(Serialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum Status {
+    Upcoming { starts_at: i64 },
+    // Open, but the participant's clock hasn't started yet
+    Ready { ends_at: i64 },
+    Running { deadline: i64 },
+    Finished,
+}
+
+// `started_at` is when the participant started, which only matters if the contest has a duration
+pub fn status(contest: &Contest, started_at: Option<i64>, now: i64) -> Status {
+    let start = contest.start.timestamp();
+    let end = contest.end.timestamp();
+
+    if now < start {
+        return Status::Upcoming {starts_at: start}
+    }
+    match (deadline(contest, started_at), started_at) {
+        (Some(deadline), _) if now < deadline => Status::Running {deadline},
+        (None, None) if now < end => Status::Ready {ends_at: end},
+        _ => Status::Finished,
+    }
+}
+
+// When submissions stop counting for a participant
+// None if they still have to start
+pub fn deadline(contest: &Contest, started_at: Option<i64>) -> Option<i64> {
+    let end = contest.end.timestamp();
+    match (contest.duration, started_at) {
+        (None, _) => Some(end),
+        (Some(duration), Some(started_at)) => Some(end.min(started_at + duration as i64)),
+        (Some(_), None) => None,
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct QuestionScore {
+    pub question: String,
+    // Wrong attempts, counted the same as on the scoreboard
+    pub attempts: usize,
+    // When the first accepted submission was made
+    pub solved_at: Option<i64>,
+}
+
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct Score {
+    pub solved: usize,
+    pub questions: Vec<QuestionScore>,
+}
+
+// Score a participant from their submissions as (question, verdict, submitted at), oldest first
+// Anything submitted after `deadline` is ignored, so scores stop changing once it passes
+pub fn score(contest: &Contest, submissions: &[(String, Verdict, i64)], deadline: Option<i64>) -> Score {
+    let questions: Vec<QuestionScore> = contest.questions.iter().map(|q| {
+        let counted: Vec<_> = submissions.iter()
+            .filter(|(question, _, at)| question == q && deadline.is_some_and(|d| *at <= d))
+            .collect();
+        let (attempts, solved) = attempts(counted.iter().map(|(_, verdict, _)| *verdict));
+        QuestionScore {question: q.to_string(), attempts, solved_at: solved.map(|i| counted[i].2)}
+    }).collect();
+
+    Score {
+        solved: questions.iter().filter(|q| q.solved_at.is_some()).count(),
+        questions,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use crate::config::LANGUAGES;
+
+    fn contest(duration: Option<u64>) -> Contest {
+        Contest {
+            handle: "test".to_string(),
+            title: String::new(),
+            questions: vec!["identity".to_string(), "both_odd".to_string()],
+            start: Utc.timestamp_opt(1000, 0).unwrap(),
+            end: Utc.timestamp_opt(2000, 0).unwrap(),
+            duration,
+            languages: LANGUAGES.to_vec(),
+            scoring: Default::default(),
+            public_scoreboard: true,
+        }
+    }
+
+    #[test]
+    fn test_status() {
+        let fixed = contest(None);
+        assert_eq!(status(&fixed, None, 500), Status::Upcoming {starts_at: 1000});
+        assert_eq!(status(&fixed, None, 1500), Status::Running {deadline: 2000});
+        assert_eq!(status(&fixed, None, 2000), Status::Finished);
+
+        let timed = contest(Some(100));
+        assert_eq!(status(&timed, None, 500), Status::Upcoming {starts_at: 1000});
+        assert_eq!(status(&timed, None, 1500), Status::Ready {ends_at: 2000});
+        assert_eq!(status(&timed, Some(1500), 1550), Status::Running {deadline: 1600});
+        assert_eq!(status(&timed, Some(1500), 1600), Status::Finished);
+        // Starting late doesn't get you past the end
+        assert_eq!(status(&timed, Some(1950), 1960), Status::Running {deadline: 2000});
+        assert_eq!(status(&timed, None, 2000), Status::Finished);
+    }
+
+    #[test]
+    fn test_score() {
+        let submissions = vec![
+            ("identity".to_string(), Verdict::WrongAnswer, 1100),
+            ("identity".to_string(), Verdict::Accepted, 1200),
+            ("identity".to_string(), Verdict::WrongAnswer, 1300),
+            ("both_odd".to_string(), Verdict::WrongAnswer, 1400),
+            ("both_odd".to_string(), Verdict::InternalError, 1500),
+            ("both_odd".to_string(), Verdict::Accepted, 2500),
+        ];
+
+        let score = score(&contest(None), &submissions, Some(2000));
+        assert_eq!(score, Score {
+            solved: 1,
+            questions: vec![
+                QuestionScore {question: "identity".to_string(), attempts: 1, solved_at: Some(1200)},
+                QuestionScore {question: "both_odd".to_string(), attempts: 1, solved_at: None},
+            ],
+        });
+    }
+}
@@ -0,0 +1,160 @@
+use super::Db;
+use super::submissions::{parse, parse_language};
+use anyhow::Result;
+use rusqlite::{params, OptionalExtension};
+use std::collections::{HashMap, HashSet};
+use crate::config::Language;
+use crate::test::Verdict;
+use crate::scoreboard::Entry;
+use super::TestResult;
+
+impl Db {
+    // Start a participant's clock, returning when it started
+    // Starting again doesn't reset it
+    pub fn start_contest(&self, contest: &str, user_id: i64, now: i64) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR IGNORE INTO contest_participants (contest, user_id, started_at) VALUES (?1, ?2, ?3)",
+            params![contest, user_id, now],
+        )?;
+        Ok(conn.query_row(
+            "SELECT started_at FROM contest_participants WHERE contest = ?1 AND user_id = ?2",
+            params![contest, user_id],
+            |r| r.get(0),
+        )?)
+    }
+
+    pub fn contest_started_at(&self, contest: &str, user_id: i64) -> Result<Option<i64>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.query_row(
+            "SELECT started_at FROM contest_participants WHERE contest = ?1 AND user_id = ?2",
+            params![contest, user_id],
+            |r| r.get(0),
+        ).optional()?)
+    }
+
+    // A participant's submissions to a contest as (question, verdict, submitted at), oldest first
+    pub fn contest_submissions(&self, contest: &str, user_id: i64) -> Result<Vec<(String, Verdict, i64)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT question, verdict, created_at FROM submissions
+             WHERE contest = ?1 AND user_id = ?2 ORDER BY created_at, id"
+        )?;
+        let submissions = stmt.query_map(
+            params![contest, user_id],
+            |r| Ok((r.get(0)?, parse(r, 1)?, r.get(2)?)),
+        )?.collect::<rusqlite::Result<_>>()?;
+        Ok(submissions)
+    }
+
+    // Everyone who has started a contest, with when they started
+    pub fn contest_participants(&self, contest: &str) -> Result<HashMap<i64, (String, i64)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT users.id, users.username, contest_participants.started_at FROM contest_participants
+             JOIN users ON users.id = contest_participants.user_id WHERE contest_participants.contest = ?1"
+        )?;
+        let participants = stmt.query_map(
+            params![contest],
+            |r| Ok((r.get(0)?, (r.get(1)?, r.get(2)?))),
+        )?.collect::<rusqlite::Result<_>>()?;
+        Ok(participants)
+    }
+
+    // Every submission to a contest, or every submission at all if `contest` is None, oldest first
+    // Tests are counted out of `total(question, language)`, since the harness' own count comes from the same
+    // process as the submission
+    pub fn scoreboard_entries(&self, contest: Option<&str>, total: impl Fn(&str, Language) -> usize) -> Result<Vec<Entry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT submissions.user_id, users.username, submissions.question, submissions.verdict,
+                submissions.tests, submissions.created_at, submissions.language
+             FROM submissions JOIN users ON users.id = submissions.user_id
+             WHERE ?1 IS NULL OR submissions.contest = ?1 ORDER BY submissions.created_at, submissions.id"
+        )?;
+        let entries = stmt.query_map(
+            params![contest],
+            |r| {
+                let question: String = r.get(2)?;
+                let tests: Vec<TestResult> = parse(r, 4)?;
+                let total = total(&question, parse_language(r, 6)?);
+                Ok(Entry {
+                    user_id: r.get(0)?,
+                    username: r.get(1)?,
+                    question,
+                    verdict: parse(r, 3)?,
+                    passed: passed(&tests, total),
+                    total,
+                    submitted_at: r.get(5)?,
+                })
+            },
+        )?.collect::<rusqlite::Result<_>>()?;
+        Ok(entries)
+    }
+}
+
+// Distinct tests passed out of the first `total`, so repeated or made up progress lines don't add anything
+fn passed(tests: &[TestResult], total: usize) -> usize {
+    tests.iter()
+        .filter(|t| t.passed && (1..=total).contains(&t.test))
+        .map(|t| t.test)
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::Reply;
+    use crate::db::NewSubmission;
+
+    #[test]
+    fn test_contests() {
+        let db = Db::memory().unwrap();
+        let alice = db.create_user("alice", "hash").unwrap().unwrap();
+
+        assert_eq!(db.contest_started_at("warmup", alice.id).unwrap(), None);
+        assert_eq!(db.start_contest("warmup", alice.id, 100).unwrap(), 100);
+        assert_eq!(db.start_contest("warmup", alice.id, 200).unwrap(), 100);
+        assert_eq!(db.contest_started_at("warmup", alice.id).unwrap(), Some(100));
+
+        let accepted = Reply::new(Verdict::Accepted, "", None);
+        db.save_submission(&NewSubmission {user_id: alice.id, contest: Some("warmup"), question: "identity", language: Language::Python, code: "", reply: &accepted, tests: &[], created_at: 150}).unwrap();
+        db.save_submission(&NewSubmission {user_id: alice.id, contest: None, question: "identity", language: Language::Python, code: "", reply: &accepted, tests: &[], created_at: 160}).unwrap();
+        db.save_submission(&NewSubmission {user_id: alice.id, contest: Some("other"), question: "identity", language: Language::Python, code: "", reply: &accepted, tests: &[], created_at: 170}).unwrap();
+
+        assert_eq!(
+            db.contest_submissions("warmup", alice.id).unwrap(),
+            vec![("identity".to_string(), Verdict::Accepted, 150)]
+        );
+
+        let mut participants = HashMap::new();
+        participants.insert(alice.id, ("alice".to_string(), 100));
+        assert_eq!(db.contest_participants("warmup").unwrap(), participants);
+
+        let entries = db.scoreboard_entries(Some("warmup"), |_, _| 4).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].username, "alice");
+        assert_eq!(entries[0].submitted_at, 150);
+        assert_eq!(db.scoreboard_entries(None, |_, _| 4).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_passed() {
+        let db = Db::memory().unwrap();
+        let alice = db.create_user("alice", "hash").unwrap().unwrap();
+
+        // Test 1 three times, then tests past the end and a made up total
+        let tests: Vec<TestResult> = [(1, 4), (1, 4), (1, 4), (2, 4), (0, 4), (5, 4), (99, 100)].iter()
+            .map(|&(test, total)| TestResult {test, total, passed: true})
+            .collect();
+        let wrong = Reply::new(Verdict::WrongAnswer, "", None);
+        db.save_submission(&NewSubmission {user_id: alice.id, contest: None, question: "identity", language: Language::Python, code: "", reply: &wrong, tests: &tests, created_at: 150}).unwrap();
+
+        let entries = db.scoreboard_entries(None, |question, language| {
+            assert_eq!((question, language), ("identity", Language::Python));
+            4
+        }).unwrap();
+        assert_eq!((entries[0].passed, entries[0].total), (2, 4));
+    }
+}
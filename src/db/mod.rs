@@ -0,0 +1,126 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub mod users;
+pub mod submissions;
+pub mod contests;
+pub mod questions;
+pub mod similarity;
+
+pub use users::{User, TokenKind, TokenInfo};
+pub use submissions::{Submission, NewSubmission, SubmissionSummary, TestResult};
+
+// Run in order on every start, so only ever add to the end
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE users (
+        id INTEGER PRIMARY KEY,
+        username TEXT NOT NULL UNIQUE,
+        password_hash TEXT NOT NULL,
+        created_at INTEGER NOT NULL
+    )",
+    // Only a hash of each token is kept, so a leaked database can't be used to log in
+    "CREATE TABLE tokens (
+        id INTEGER PRIMARY KEY,
+        token_hash TEXT NOT NULL UNIQUE,
+        user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        kind TEXT NOT NULL,
+        name TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        expires_at INTEGER
+    )",
+    "CREATE INDEX tokens_user ON tokens(user_id)",
+    // `verdict`, `tests` and `usage` are json
+    "CREATE TABLE submissions (
+        id INTEGER PRIMARY KEY,
+        user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        question TEXT NOT NULL,
+        language TEXT NOT NULL,
+        code TEXT NOT NULL,
+        verdict TEXT NOT NULL,
+        msg TEXT NOT NULL,
+        tests TEXT NOT NULL,
+        usage TEXT,
+        created_at INTEGER NOT NULL
+    )",
+    "CREATE INDEX submissions_user_question ON submissions(user_id, question)",
+    // Null for practice submissions
+    "ALTER TABLE submissions ADD COLUMN contest TEXT",
+    "CREATE INDEX submissions_contest ON submissions(contest, user_id)",
+    "CREATE TABLE contest_participants (
+        contest TEXT NOT NULL,
+        user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        started_at INTEGER NOT NULL,
+        PRIMARY KEY (contest, user_id)
+    )",
+    // Questions added or edited through the admin api, which replace any in the config with the same handle
+    // `question` is json
+    "CREATE TABLE questions (
+        handle TEXT PRIMARY KEY,
+        question TEXT NOT NULL,
+        enabled INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    )",
+    // Only set from the config's admins on start, or by `executor admin`, never through the api
+    "ALTER TABLE users ADD COLUMN admin INTEGER NOT NULL DEFAULT 0",
+    // The batch similarity check's last pairs for each question, language and contest
+    // `contest` is empty for every submission, `pairs` is json
+    "CREATE TABLE similarity_reports (
+        question TEXT NOT NULL,
+        language TEXT NOT NULL,
+        contest TEXT NOT NULL,
+        pairs TEXT NOT NULL,
+        checked_at INTEGER NOT NULL,
+        PRIMARY KEY (question, language, contest)
+    )",
+];
+
+// Everything we keep between restarts
+// Queries are short, so they just run on whatever thread asks for them
+pub struct Db {
+    conn: Mutex<Connection>,
+}
+
+impl Db {
+    pub fn open(path: &str) -> Result<Db> {
+        Db::new(Connection::open(path)?)
+    }
+
+    // Starts empty every time, for tests
+    pub fn memory() -> Result<Db> {
+        Db::new(Connection::open_in_memory()?)
+    }
+
+    fn new(mut conn: Connection) -> Result<Db> {
+        conn.execute_batch("PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;")?;
+
+        let applied: usize = conn.query_row("PRAGMA user_version", rusqlite::NO_PARAMS, |r| r.get::<_, i64>(0))? as usize;
+        let tx = conn.transaction()?;
+        for migration in MIGRATIONS.iter().skip(applied) {
+            tx.execute_batch(migration)?;
+        }
+        tx.execute_batch(&format!("PRAGMA user_version = {}", MIGRATIONS.len()))?;
+        tx.commit()?;
+
+        Ok(Db {conn: Mutex::new(conn)})
+    }
+}
+
+// Seconds since the epoch, which is how every time is stored
+pub fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_migrate() {
+        let db = Db::memory().unwrap();
+        let conn = db.conn.lock().unwrap();
+        let version: i64 = conn.query_row("PRAGMA user_version", rusqlite::NO_PARAMS, |r| r.get(0)).unwrap();
+        assert_eq!(version as usize, MIGRATIONS.len());
+    }
+}
@@ -0,0 +1,56 @@
+use super::Db;
+use super::submissions::parse;
+use anyhow::Result;
+use rusqlite::params;
+use crate::config::Question;
+
+impl Db {
+    // Every question saved through the admin api, with whether it's enabled
+    pub fn saved_questions(&self) -> Result<Vec<(Question, bool)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT question, enabled FROM questions ORDER BY handle")?;
+        let questions = stmt.query_map(
+            rusqlite::NO_PARAMS,
+            |r| Ok((parse(r, 0)?, r.get(1)?)),
+        )?.collect::<rusqlite::Result<_>>()?;
+        Ok(questions)
+    }
+
+    // Adds the question, or replaces the one with the same handle
+    pub fn save_question(&self, question: &Question, enabled: bool, now: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO questions (handle, question, enabled, updated_at) VALUES (?1, ?2, ?3, ?4)",
+            params![question.handle, serde_json::to_string(question)?, enabled, now],
+        )?;
+        Ok(())
+    }
+
+    // Returns whether there was one to delete
+    pub fn delete_question(&self, handle: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("DELETE FROM questions WHERE handle = ?1", params![handle])? > 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_questions() {
+        let db = Db::memory().unwrap();
+        let mut question = Question {handle: "reverse".to_string(), function_name: "reverse".to_string(), ..Question::default()};
+
+        db.save_question(&question, false, 100).unwrap();
+        assert_eq!(db.saved_questions().unwrap(), vec![(question.clone(), false)]);
+
+        question.prompt = "Reverse a list.".to_string();
+        db.save_question(&question, true, 200).unwrap();
+        assert_eq!(db.saved_questions().unwrap(), vec![(question, true)]);
+
+        assert!(db.delete_question("reverse").unwrap());
+        assert!(!db.delete_question("reverse").unwrap());
+        assert!(db.saved_questions().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,50 @@
+use super::Db;
+use super::submissions::parse;
+use anyhow::Result;
+use rusqlite::{params, OptionalExtension};
+use crate::config::Language;
+use crate::similarity::{Pair, Report};
+
+impl Db {
+    // Replaces the last report for the question, language and contest, where None is every submission
+    pub fn save_similarity_report(&self, question: &str, language: Language, contest: Option<&str>, pairs: &[Pair], now: i64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO similarity_reports (question, language, contest, pairs, checked_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![question, language.name(), contest.unwrap_or(""), serde_json::to_string(pairs)?, now],
+        )?;
+        Ok(())
+    }
+
+    // None until the batch job has checked it
+    pub fn similarity_report(&self, question: &str, language: Language, contest: Option<&str>) -> Result<Option<Report>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.query_row(
+            "SELECT pairs, checked_at FROM similarity_reports WHERE question = ?1 AND language = ?2 AND contest = ?3",
+            params![question, language.name(), contest.unwrap_or("")],
+            |r| Ok(Report {pairs: parse(r, 0)?, checked_at: r.get(1)?}),
+        ).optional()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::similarity::Side;
+
+    #[test]
+    fn test_similarity_reports() {
+        let db = Db::memory().unwrap();
+        let side = |submission| Side {submission, username: format!("user{}", submission), regions: vec![(0, 10)]};
+        let pairs = vec![Pair {similarity: 0.9, first: side(1), second: side(2)}];
+
+        assert_eq!(db.similarity_report("identity", Language::Python, None).unwrap(), None);
+        db.save_similarity_report("identity", Language::Python, None, &pairs, 100).unwrap();
+        db.save_similarity_report("identity", Language::Python, Some("warmup"), &[], 100).unwrap();
+        assert_eq!(db.similarity_report("identity", Language::Python, None).unwrap(), Some(Report {pairs, checked_at: 100}));
+        assert_eq!(db.similarity_report("identity", Language::Java, None).unwrap(), None);
+
+        db.save_similarity_report("identity", Language::Python, None, &[], 200).unwrap();
+        assert_eq!(db.similarity_report("identity", Language::Python, None).unwrap(), Some(Report {pairs: Vec::new(), checked_at: 200}));
+    }
+}
@@ -0,0 +1,225 @@
+use super::Db;
+use anyhow::Result;
+use rusqlite::{params, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::config::Language;
+use crate::exec::Usage;
+use crate::similarity::Source;
+use crate::test::{Reply, Verdict};
+
+// How one test case went, from the harness' progress
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TestResult {
+    // Counts from 1
+    pub test: usize,
+    // Submissions saved before this was kept have 0
+    #[serde(default)]
+    pub total: usize,
+    pub passed: bool,
+}
+
+// A judged submission
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct Submission {
+    pub id: i64,
+    pub user_id: i64,
+    pub contest: Option<String>,
+    pub question: String,
+    pub language: Language,
+    pub code: String,
+    pub verdict: Verdict,
+    pub msg: String,
+    pub tests: Vec<TestResult>,
+    pub usage: Option<Usage>,
+    // When it was submitted, not when it was judged
+    pub created_at: i64,
+}
+
+// A judged submission, before it's saved
+#[derive(Debug, Clone, Copy)]
+pub struct NewSubmission<'a> {
+    pub user_id: i64,
+    // None for practice
+    pub contest: Option<&'a str>,
+    pub question: &'a str,
+    pub language: Language,
+    pub code: &'a str,
+    pub reply: &'a Reply,
+    pub tests: &'a [TestResult],
+    // When it was submitted, not when it was judged
+    pub created_at: i64,
+}
+
+// A submission without its code and output, for lists
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct SubmissionSummary {
+    pub id: i64,
+    pub question: String,
+    pub language: Language,
+    pub verdict: Verdict,
+    pub usage: Option<Usage>,
+    pub created_at: i64,
+}
+
+// Columns are stored as text, so anything that doesn't parse back is a bug
+pub(super) fn parse<T: for<'de> Deserialize<'de>>(row: &Row, i: usize) -> rusqlite::Result<T> {
+    let s: String = row.get(i)?;
+    serde_json::from_str(&s).map_err(|e| rusqlite::Error::FromSqlConversionFailure(i, rusqlite::types::Type::Text, Box::new(e)))
+}
+
+fn parse_usage(row: &Row, i: usize) -> rusqlite::Result<Option<Usage>> {
+    match row.get::<_, Option<String>>(i)? {
+        Some(_) => parse(row, i).map(Some),
+        None => Ok(None),
+    }
+}
+
+pub(super) fn parse_language(row: &Row, i: usize) -> rusqlite::Result<Language> {
+    let s: String = row.get(i)?;
+    s.parse().map_err(|e: anyhow::Error| rusqlite::Error::FromSqlConversionFailure(i, rusqlite::types::Type::Text, e.into()))
+}
+
+impl Db {
+    pub fn save_submission(&self, submission: &NewSubmission) -> Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        let NewSubmission {user_id, contest, question, language, code, reply, tests, created_at} = *submission;
+        let usage = reply.usage.as_ref().map(serde_json::to_string).transpose()?;
+        conn.execute(
+            "INSERT INTO submissions (user_id, contest, question, language, code, verdict, msg, tests, usage, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                user_id, contest, question, language.name(), code,
+                serde_json::to_string(&reply.verdict)?, reply.msg, serde_json::to_string(tests)?, usage,
+                created_at,
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    pub fn submission(&self, id: i64) -> Result<Option<Submission>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.query_row(
+            "SELECT id, user_id, contest, question, language, code, verdict, msg, tests, usage, created_at
+             FROM submissions WHERE id = ?1",
+            params![id],
+            |r| Ok(Submission {
+                id: r.get(0)?,
+                user_id: r.get(1)?,
+                contest: r.get(2)?,
+                question: r.get(3)?,
+                language: parse_language(r, 4)?,
+                code: r.get(5)?,
+                verdict: parse(r, 6)?,
+                msg: r.get(7)?,
+                tests: parse(r, 8)?,
+                usage: parse_usage(r, 9)?,
+                created_at: r.get(10)?,
+            }),
+        ).optional()?)
+    }
+
+    // Newest first
+    pub fn submissions(&self, user_id: i64, question: &str) -> Result<Vec<SubmissionSummary>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, question, language, verdict, usage, created_at
+             FROM submissions WHERE user_id = ?1 AND question = ?2 ORDER BY id DESC"
+        )?;
+        let submissions = stmt.query_map(
+            params![user_id, question],
+            |r| Ok(SubmissionSummary {
+                id: r.get(0)?,
+                question: r.get(1)?,
+                language: parse_language(r, 2)?,
+                verdict: parse(r, 3)?,
+                usage: parse_usage(r, 4)?,
+                created_at: r.get(5)?,
+            }),
+        )?.collect::<rusqlite::Result<_>>()?;
+        Ok(submissions)
+    }
+
+    // Everyone's latest submission to a question in a language, oldest first
+    // `contest` limits it to submissions made in that contest
+    pub fn latest_submissions(&self, question: &str, language: Language, contest: Option<&str>) -> Result<Vec<Source>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT submissions.id, users.username, submissions.code
+             FROM submissions JOIN users ON users.id = submissions.user_id
+             WHERE submissions.id IN (
+                SELECT MAX(id) FROM submissions
+                WHERE question = ?1 AND language = ?2 AND (?3 IS NULL OR contest = ?3) GROUP BY user_id
+             ) ORDER BY submissions.id"
+        )?;
+        let sources = stmt.query_map(
+            params![question, language.name(), contest],
+            |r| Ok(Source {submission: r.get(0)?, username: r.get(1)?, code: r.get(2)?}),
+        )?.collect::<rusqlite::Result<_>>()?;
+        Ok(sources)
+    }
+
+    // Every question the user has submitted to, with whether any submission was accepted
+    pub fn attempted_questions(&self, user_id: i64) -> Result<HashMap<String, bool>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT question, MAX(verdict = ?2) FROM submissions WHERE user_id = ?1 GROUP BY question"
+        )?;
+        let attempted = stmt.query_map(
+            params![user_id, serde_json::to_string(&Verdict::Accepted)?],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )?.collect::<rusqlite::Result<_>>()?;
+        Ok(attempted)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_submissions() {
+        let db = Db::memory().unwrap();
+        let alice = db.create_user("alice", "hash").unwrap().unwrap();
+        let bob = db.create_user("bob", "hash").unwrap().unwrap();
+
+        let usage = Usage {wall_ms: 10, user_ms: 5, sys_ms: 1, peak_memory: 1024, oom_killed: false};
+        let wrong = Reply::new(Verdict::WrongAnswer, "Nope", Some(usage.clone()));
+        let tests = [TestResult {test: 1, total: 2, passed: true}, TestResult {test: 2, total: 2, passed: false}];
+        let first = db.save_submission(&NewSubmission {user_id: alice.id, contest: None, question: "identity", language: Language::Python, code: "def identity(x): pass", reply: &wrong, tests: &tests, created_at: 100}).unwrap();
+        let second = db.save_submission(&NewSubmission {user_id: alice.id, contest: None, question: "identity", language: Language::Java, code: "class", reply: &Reply::internal_error(), tests: &[], created_at: 200}).unwrap();
+        db.save_submission(&NewSubmission {user_id: alice.id, contest: None, question: "both_odd", language: Language::Python, code: "", reply: &Reply::internal_error(), tests: &[], created_at: 300}).unwrap();
+        db.save_submission(&NewSubmission {user_id: bob.id, contest: None, question: "identity", language: Language::Python, code: "", reply: &Reply::internal_error(), tests: &[], created_at: 400}).unwrap();
+
+        assert_eq!(db.submission(first).unwrap(), Some(Submission {
+            id: first,
+            user_id: alice.id,
+            contest: None,
+            question: "identity".to_string(),
+            language: Language::Python,
+            code: "def identity(x): pass".to_string(),
+            verdict: Verdict::WrongAnswer,
+            msg: "Nope".to_string(),
+            tests: tests.to_vec(),
+            usage: Some(usage),
+            created_at: 100,
+        }));
+        assert_eq!(db.submission(1000).unwrap(), None);
+
+        let history = db.submissions(alice.id, "identity").unwrap();
+        assert_eq!(history.iter().map(|s| s.id).collect::<Vec<_>>(), vec![second, first]);
+        assert_eq!(history[0].usage, None);
+        assert_eq!(history[0].verdict, Verdict::InternalError);
+
+        let latest = db.latest_submissions("identity", Language::Python, None).unwrap();
+        assert_eq!(latest.iter().map(|s| &s.username[..]).collect::<Vec<_>>(), vec!["alice", "bob"]);
+        assert_eq!(latest[0].submission, first);
+        assert!(db.latest_submissions("identity", Language::Python, Some("warmup")).unwrap().is_empty());
+
+        db.save_submission(&NewSubmission {user_id: alice.id, contest: None, question: "both_odd", language: Language::Python, code: "", reply: &Reply::new(Verdict::Accepted, "", None), tests: &[], created_at: 500}).unwrap();
+        let mut attempted = HashMap::new();
+        attempted.insert("identity".to_string(), false);
+        attempted.insert("both_odd".to_string(), true);
+        assert_eq!(db.attempted_questions(alice.id).unwrap(), attempted);
+    }
+}
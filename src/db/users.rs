@@ -0,0 +1,178 @@
+use super::{Db, now};
+use anyhow::Result;
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+    pub created_at: i64,
+    // Shown as part of an `Account` instead
+    #[serde(skip)]
+    pub admin: bool,
+}
+
+impl User {
+    // Kept on the account rather than matched by name, so nobody can sign up as an admin
+    pub fn is_admin(&self) -> bool {
+        self.admin
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenKind {
+    // Made by logging in, and expires
+    Session,
+    // Made on request for scripts, and lasts until it's deleted
+    Api,
+}
+
+impl TokenKind {
+    fn name(self) -> &'static str {
+        match self {
+            TokenKind::Session => "session",
+            TokenKind::Api => "api",
+        }
+    }
+}
+
+// A token as shown to its owner, which never includes the token itself
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct TokenInfo {
+    pub id: i64,
+    pub name: String,
+    pub created_at: i64,
+}
+
+impl Db {
+    // Returns None if the username is taken
+    pub fn create_user(&self, username: &str, password_hash: &str) -> Result<Option<User>> {
+        let conn = self.conn.lock().unwrap();
+        let created_at = now();
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO users (username, password_hash, created_at) VALUES (?1, ?2, ?3)",
+            params![username, password_hash, created_at],
+        )?;
+        if inserted == 0 {
+            return Ok(None)
+        }
+        Ok(Some(User {id: conn.last_insert_rowid(), username: username.to_string(), created_at, admin: false}))
+    }
+
+    // Gets a user along with their password hash
+    pub fn user_by_name(&self, username: &str) -> Result<Option<(User, String)>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.query_row(
+            "SELECT id, username, created_at, admin, password_hash FROM users WHERE username = ?1",
+            params![username],
+            |r| Ok((User {id: r.get(0)?, username: r.get(1)?, created_at: r.get(2)?, admin: r.get(3)?}, r.get(4)?)),
+        ).optional()?)
+    }
+
+    // Returns false if there's no such user
+    pub fn set_admin(&self, username: &str, admin: bool) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("UPDATE users SET admin = ?1 WHERE username = ?2", params![admin, username])? > 0)
+    }
+
+    // `expires_at` is None for tokens that last until they're deleted
+    pub fn create_token(&self, user_id: i64, token_hash: &str, kind: TokenKind, name: &str, expires_at: Option<i64>) -> Result<TokenInfo> {
+        let conn = self.conn.lock().unwrap();
+        let created_at = now();
+        conn.execute(
+            "INSERT INTO tokens (token_hash, user_id, kind, name, created_at, expires_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![token_hash, user_id, kind.name(), name, created_at, expires_at],
+        )?;
+        Ok(TokenInfo {id: conn.last_insert_rowid(), name: name.to_string(), created_at})
+    }
+
+    // Returns None if there's no such token or it has expired
+    pub fn user_by_token(&self, token_hash: &str) -> Result<Option<User>> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.query_row(
+            "SELECT users.id, users.username, users.created_at, users.admin FROM tokens JOIN users ON users.id = tokens.user_id
+             WHERE tokens.token_hash = ?1 AND (tokens.expires_at IS NULL OR tokens.expires_at > ?2)",
+            params![token_hash, now()],
+            |r| Ok(User {id: r.get(0)?, username: r.get(1)?, created_at: r.get(2)?, admin: r.get(3)?}),
+        ).optional()?)
+    }
+
+    pub fn delete_token(&self, token_hash: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("DELETE FROM tokens WHERE token_hash = ?1", params![token_hash])? > 0)
+    }
+
+    // Only api tokens, sessions come and go on their own
+    pub fn api_tokens(&self, user_id: i64) -> Result<Vec<TokenInfo>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, name, created_at FROM tokens WHERE user_id = ?1 AND kind = ?2 ORDER BY id"
+        )?;
+        let tokens = stmt.query_map(
+            params![user_id, TokenKind::Api.name()],
+            |r| Ok(TokenInfo {id: r.get(0)?, name: r.get(1)?, created_at: r.get(2)?}),
+        )?.collect::<rusqlite::Result<_>>()?;
+        Ok(tokens)
+    }
+
+    // Returns false if the user has no api token with that id
+    pub fn delete_api_token(&self, user_id: i64, id: i64) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute(
+            "DELETE FROM tokens WHERE id = ?1 AND user_id = ?2 AND kind = ?3",
+            params![id, user_id, TokenKind::Api.name()],
+        )? > 0)
+    }
+
+    // Drop sessions that can't be used anymore
+    pub fn delete_expired_tokens(&self) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("DELETE FROM tokens WHERE expires_at <= ?1", params![now()])?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_users() {
+        let db = Db::memory().unwrap();
+
+        let user = db.create_user("alice", "hash").unwrap().unwrap();
+        assert!(db.create_user("alice", "other").unwrap().is_none());
+        assert_eq!(db.user_by_name("alice").unwrap(), Some((user.clone(), "hash".to_string())));
+        assert_eq!(db.user_by_name("bob").unwrap(), None);
+
+        assert!(!user.is_admin());
+        assert!(db.set_admin("alice", true).unwrap());
+        assert!(db.user_by_name("alice").unwrap().unwrap().0.is_admin());
+        assert!(!db.set_admin("bob", true).unwrap());
+    }
+
+    #[test]
+    fn test_tokens() {
+        let db = Db::memory().unwrap();
+        let user = db.create_user("alice", "hash").unwrap().unwrap();
+
+        db.create_token(user.id, "session", TokenKind::Session, "login", Some(now() + 60)).unwrap();
+        db.create_token(user.id, "expired", TokenKind::Session, "login", Some(now() - 1)).unwrap();
+        let api = db.create_token(user.id, "api", TokenKind::Api, "ci", None).unwrap();
+
+        assert_eq!(db.user_by_token("session").unwrap(), Some(user.clone()));
+        assert_eq!(db.user_by_token("api").unwrap(), Some(user.clone()));
+        assert_eq!(db.user_by_token("expired").unwrap(), None);
+        assert_eq!(db.user_by_token("nothing").unwrap(), None);
+        assert_eq!(db.api_tokens(user.id).unwrap(), vec![api.clone()]);
+
+        assert_eq!(db.delete_expired_tokens().unwrap(), 1);
+        assert!(db.delete_token("session").unwrap());
+        assert_eq!(db.user_by_token("session").unwrap(), None);
+
+        assert!(!db.delete_api_token(user.id + 1, api.id).unwrap());
+        assert!(db.delete_api_token(user.id, api.id).unwrap());
+        assert_eq!(db.user_by_token("api").unwrap(), None);
+    }
+}
@@ -1,18 +1,50 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::str;
-use log::error;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use log::{debug, error, warn};
 use std::process::{Command, Stdio};
+use lazy_static::lazy_static;
+use crate::metrics;
 
-use super::{CodeExec, exec_timed};
+use super::{CodeExec, Usage, Lines, exec_timed};
+
+// Memory each container gets, the same limit is used for swap and kernel memory
+pub const MEMORY_LIMIT: &str = "100m";
+pub const MEMORY_LIMIT_BYTES: u64 = 100 * 1024 * 1024;
+
+// cgroup files that usage is read from, for both cgroup v2 and v1 hosts
+const USAGE_FILES: [&str; 6] = [
+    "cpu.stat", "memory.peak", "memory.events",
+    "cpuacct/cpuacct.stat", "memory/memory.max_usage_in_bytes", "memory/memory.oom_control",
+];
+
+lazy_static! {
+    // Containers that are currently alive, so they can be cleaned up on shutdown
+    static ref CONTAINERS: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
 
 #[derive(Debug)]
 pub struct DockerID {
     id: String
 }
 
+impl DockerID {
+    fn new(id: String) -> DockerID {
+        let mut containers = CONTAINERS.lock().unwrap();
+        containers.insert(id.clone());
+        metrics::CONTAINERS.set(containers.len() as i64);
+        DockerID{id}
+    }
+}
+
 impl Drop for DockerID {
     fn drop(&mut self) {
-        println!("Killing {:?}", self);
+        debug!("Killing container {}", self.id);
+        let mut containers = CONTAINERS.lock().unwrap();
+        containers.remove(&self.id);
+        metrics::CONTAINERS.set(containers.len() as i64);
+        drop(containers);
         kill_container(&self.id);
     }
 }
@@ -20,8 +52,8 @@ impl Drop for DockerID {
 // TODO: Also I think it would be nice to disable syscalls
 fn gen_args<'a>(image_name: &'a str, command: impl IntoIterator<Item=&'a str>, extra_args: impl IntoIterator<Item=&'a str>, readonly: bool) -> Vec<&str> {
     let mut args = vec!["run", "--rm", "-i",
-        "--network", "none", "--user", "1000", "-m", "100m", "--memory-swap", "100m",
-        "--kernel-memory", "100m"];
+        "--network", "none", "--user", "1000", "-m", MEMORY_LIMIT, "--memory-swap", MEMORY_LIMIT,
+        "--kernel-memory", MEMORY_LIMIT];
 
     args.extend(extra_args);
 
@@ -37,7 +69,7 @@ fn gen_args<'a>(image_name: &'a str, command: impl IntoIterator<Item=&'a str>, e
 }
 
 
-// This creates dangling containers if the command times out.
+// The container is named so it can be tracked, and killed if the command times out or on shutdown
 pub async fn exec_in_container<'a>(
     image_name: &'a str,
     command: impl IntoIterator<Item=&'a str>,
@@ -46,13 +78,17 @@ pub async fn exec_in_container<'a>(
     readonly: bool
     ) -> Result<CodeExec, anyhow::Error> {
 
-    let args = gen_args(image_name, command, vec!["-a", "STDIN", "-a", "STDOUT", "-a", "STDERR"], readonly);
+    // Killing it once it has exited with --rm does nothing
+    let container = DockerID::new(format!("code-quiz-{:016x}", rand::random::<u64>()));
+    let command = command.into_iter().map(|c| -> &str { c });
+    let args = gen_args(image_name, command, vec!["--name", &container.id, "-a", "STDIN", "-a", "STDOUT", "-a", "STDERR"], readonly);
 
     exec_timed(
         "docker",
         args,
         time,
-        stdin
+        stdin,
+        None
     ).await
 }
 
@@ -63,14 +99,23 @@ pub async fn exec_in_dangling_container(
     time: Duration,
     root: bool,
     stdin: Option<&str>,
-    readonly: bool
+    readonly: bool,
+    stderr_lines: Option<&Lines>,
 )
--> Result<CodeExec, anyhow::Error>
+-> Result<(CodeExec, Option<Usage>), anyhow::Error>
 {
     let container = dangling_container(image_name, vec!["sh"], Duration::from_secs(2), None, readonly).await?;
 
-    let ret = run_in_container(&container, command.into_iter().collect::<Vec<&str>>(), time, root, stdin).await?;
-    Ok(ret)
+    let start = Instant::now();
+    let ret = run_in_container(&container, command.into_iter().collect::<Vec<&str>>(), time, root, stdin, stderr_lines).await?;
+    let wall_time = start.elapsed();
+
+    // Stats can be gone after a timeout or OOM kill, which shouldn't cost the submission its verdict
+    let usage = container_usage(&container).await
+        .map_err(|e| warn!("Failed to read container usage: {}", e))
+        .ok()
+        .map(|usage| Usage {wall_ms: wall_time.as_millis() as u64, ..usage});
+    Ok((ret, usage))
 }
 
 
@@ -83,9 +128,22 @@ pub async fn dangling_container<'a>(
 ) -> Result<DockerID, anyhow::Error> {
     let args = gen_args(image_name, command, vec!["-t", "-d"], readonly);
 
-    match exec_timed("docker", args, time, stdin).await? {
-        CodeExec::Executed(Some(0), stdout, _) => Ok(DockerID{id: stdout.trim().to_string()}),
-        e => Err(anyhow::Error::msg(format!("Failed to start container: {:?}", e)))
+    let start = Instant::now();
+    let started = exec_timed("docker", args, time, stdin, None).await;
+    match started {
+        Ok(CodeExec::Executed(Some(0), stdout, _)) => {
+            metrics::CONTAINER_START.with_label_values(&[image_name]).observe(start.elapsed().as_secs_f64());
+            let id = DockerID::new(String::from_utf8_lossy(&stdout).trim().to_string());
+            debug!("Started container {} from {} in {:?}", id.id, image_name, start.elapsed());
+            Ok(id)
+        },
+        e => {
+            metrics::CONTAINER_START_FAILURES.with_label_values(&[image_name]).inc();
+            match e {
+                Err(e) => Err(e),
+                Ok(e) => Err(anyhow::Error::msg(format!("Failed to start container: {:?}", e))),
+            }
+        },
     }
 }
 
@@ -95,6 +153,7 @@ pub async fn run_in_container<'a>(
     time: Duration,
     root: bool,
     stdin: Option<&str>,
+    stderr_lines: Option<&Lines>,
 ) -> Result<CodeExec, anyhow::Error>
 {
     let mut args = vec!["exec", "-i"];
@@ -107,7 +166,52 @@ pub async fn run_in_container<'a>(
     args.push(&container_id.id);
     args.extend(command);
 
-    exec_timed("docker", args, time, stdin).await
+    exec_timed("docker", args, time, stdin, stderr_lines).await
+}
+
+// Resource usage of everything that has run in the container, read from its cgroup
+// Wall time isn't known here, so it is left as 0
+pub async fn container_usage(container_id: &DockerID) -> Result<Usage, anyhow::Error> {
+    let script = format!(
+        "cd /sys/fs/cgroup && for f in {}; do [ -r $f ] && echo \"== $f\" && cat $f; done; true",
+        USAGE_FILES.join(" ")
+    );
+
+    match run_in_container(container_id, vec!["sh", "-c", &script], Duration::from_secs(2), false, None, None).await? {
+        CodeExec::Executed(Some(0), stdout, _) => Ok(parse_usage(&String::from_utf8_lossy(&stdout))),
+        e => Err(anyhow::Error::msg(format!("Failed to read container usage: {:?}", e)))
+    }
+}
+
+// Parses the output of the script in `container_usage`
+// Each file is preceded by a `== <file>` line
+fn parse_usage(stats: &str) -> Usage {
+    let mut usage = Usage::default();
+    let mut file = "";
+
+    for line in stats.lines() {
+        if let Some(f) = line.strip_prefix("== ") {
+            file = f;
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let value = |v: &str| v.parse::<u64>().unwrap_or(0);
+        match (file, &fields[..]) {
+            ("cpu.stat", ["user_usec", v]) => usage.user_ms = value(v) / 1000,
+            ("cpu.stat", ["system_usec", v]) => usage.sys_ms = value(v) / 1000,
+            // cgroup v1 reports cpu time in USER_HZ, which is 100 on linux
+            ("cpuacct/cpuacct.stat", ["user", v]) => usage.user_ms = value(v) * 10,
+            ("cpuacct/cpuacct.stat", ["system", v]) => usage.sys_ms = value(v) * 10,
+            ("memory.peak", [v]) | ("memory/memory.max_usage_in_bytes", [v]) => usage.peak_memory = value(v),
+            ("memory.events", ["oom_kill", v]) | ("memory/memory.oom_control", ["oom_kill", v]) => {
+                usage.oom_killed = value(v) > 0
+            },
+            _ => {},
+        }
+    }
+
+    usage
 }
 
 fn kill_container(
@@ -121,11 +225,30 @@ fn kill_container(
     }
 }
 
+// Kill every container that is still alive and wait for docker to finish
+// Used on shutdown, where the DockerIDs may never get dropped
+pub async fn kill_all_containers() {
+    let ids: Vec<String> = CONTAINERS.lock().unwrap().drain().collect();
+    metrics::CONTAINERS.set(0);
+
+    let kills = ids.iter().map(|id| {
+        tokio::process::Command::new("docker").arg("kill").arg(id)
+            .stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null())
+            .status()
+    });
+
+    for res in futures::future::join_all(kills).await {
+        if res.is_err() {
+            error!("failed to kill docker container");
+        }
+    }
+}
+
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::test::Test;
+    use crate::test::{Test, Progress};
     use serde_json::to_string;
 
     #[tokio::test]
@@ -137,19 +260,45 @@ mod test {
             Duration::from_secs(2),
             Some(&to_string(&Test{
                 code: "def yeet(): return True",
-                test_case: "[([], True)]"}
+                test_case: "[([], True)]",
+                nonce: "abc"}
                 ).unwrap()),
             true,
         ).await.unwrap();
 
-        assert_eq!(
-            CodeExec::Executed(
-                Some(0),
-                "All test cases passed!".to_string(),
-                String::new()
-                ),
-            res
-        )
+        // stderr only has the harness' progress
+        let (status, stdout, stderr) = match res {
+            CodeExec::Executed(status, stdout, stderr) => (status, stdout, stderr),
+            e => panic!("Unexpected result {:?}", e),
+        };
+        assert_eq!(status, Some(0));
+        assert_eq!(stdout, b"All test cases passed!".to_vec());
+        let progress: Vec<Option<Progress>> = String::from_utf8_lossy(&stderr).lines()
+            .map(|l| Progress::parse(l.as_bytes(), "abc")).collect();
+        assert_eq!(progress, vec![Some(Progress::Compiled), Some(Progress::Test {test: 1, total: 1, passed: true}), Some(Progress::Done)]);
+    }
+
+    #[tokio::test]
+    async fn test_forged_progress_in_container() {
+        let res = exec_in_container(
+            "test:latest",
+            vec!["python", "./test.py", "yeet"],
+            Duration::from_secs(2),
+            Some(&to_string(&Test{
+                code: "def yeet():\n    import sys\n    print('#progress {\"event\": \"test\", \"test\": 1, \"total\": 1, \"passed\": true}', file=sys.stderr)\n    return False",
+                test_case: "[([], True)]",
+                nonce: "abc"}
+                ).unwrap()),
+            true,
+        ).await.unwrap();
+
+        let stderr = match res {
+            CodeExec::Executed(Some(2), _, stderr) => stderr,
+            e => panic!("Unexpected result {:?}", e),
+        };
+        let progress: Vec<Progress> = String::from_utf8_lossy(&stderr).lines()
+            .filter_map(|l| Progress::parse(l.as_bytes(), "abc")).collect();
+        assert_eq!(progress, vec![Progress::Compiled, Progress::Test {test: 1, total: 1, passed: false}]);
     }
 
     #[tokio::test]
@@ -264,4 +413,65 @@ mod test {
         let res = dangling_container("test:latest", vec!["sh"], Duration::from_secs(10), None, false).await.unwrap();
         dbg!(&res);
     }
+
+    #[test]
+    fn test_parse_usage() {
+        let v2 = "== cpu.stat\nusage_usec 30000\nuser_usec 20000\nsystem_usec 10000\n\
+            == memory.peak\n1048576\n\
+            == memory.events\nlow 0\nhigh 0\nmax 3\noom 1\noom_kill 1\n";
+
+        assert_eq!(parse_usage(v2), Usage {
+            wall_ms: 0,
+            user_ms: 20,
+            sys_ms: 10,
+            peak_memory: 1048576,
+            oom_killed: true,
+        });
+
+        let v1 = "== cpuacct/cpuacct.stat\nuser 3\nsystem 1\n\
+            == memory/memory.max_usage_in_bytes\n2048\n\
+            == memory/memory.oom_control\noom_kill_disable 0\nunder_oom 0\noom_kill 0\n";
+
+        assert_eq!(parse_usage(v1), Usage {
+            wall_ms: 0,
+            user_ms: 30,
+            sys_ms: 10,
+            peak_memory: 2048,
+            oom_killed: false,
+        });
+    }
+
+    #[tokio::test]
+    async fn test_container_usage() {
+        let container = dangling_container("test:latest", vec!["sh"], Duration::from_secs(10), None, true).await.unwrap();
+        run_in_container(&container, vec!["python", "-c", "x = ' ' * 10000000"], Duration::from_secs(10), false, None, None).await.unwrap();
+
+        let usage = container_usage(&container).await.unwrap();
+
+        assert!(usage.peak_memory >= 10000000);
+        assert!(!usage.oom_killed);
+    }
+
+    #[tokio::test]
+    async fn test_exec_in_container_tracked() {
+        let before = CONTAINERS.lock().unwrap().clone();
+        let run = tokio::spawn(exec_in_container("test:latest", vec!["sleep", "2"], Duration::from_secs(10), None, true));
+        tokio::time::delay_for(Duration::from_secs(1)).await;
+        let started: Vec<String> = CONTAINERS.lock().unwrap().difference(&before)
+            .filter(|id| id.starts_with("code-quiz-")).cloned().collect();
+        assert!(!started.is_empty());
+
+        run.await.unwrap().unwrap();
+        assert!(started.iter().all(|id| !CONTAINERS.lock().unwrap().contains(id)));
+    }
+
+    #[tokio::test]
+    async fn test_kill_all_containers() {
+        let res = dangling_container("test:latest", vec!["sh"], Duration::from_secs(10), None, false).await.unwrap();
+        assert!(CONTAINERS.lock().unwrap().contains(&res.id));
+
+        kill_all_containers().await;
+
+        assert!(!CONTAINERS.lock().unwrap().contains(&res.id));
+    }
 }
@@ -1,21 +1,31 @@
-use std::time::Duration;
-use super::CodeExec;
-use super::docker::{dangling_container, run_in_container};
+use std::time::{Duration, Instant};
+use log::warn;
+use super::{CodeExec, Usage, Lines};
+use super::docker::{dangling_container, run_in_container, container_usage};
 
-pub async fn exec_java_in_container(source: &str, image_name: &str, time: Duration, stdin: Option<&str>)
--> Result<CodeExec, anyhow::Error>
+pub async fn exec_java_in_container(source: &str, image_name: &str, time: Duration, stdin: Option<&str>, stderr_lines: Option<&Lines>)
+-> Result<(CodeExec, Option<Usage>), anyhow::Error>
 {
     let container = dangling_container(image_name, vec!["sh"], Duration::from_secs(2), None, false).await?;
 
-    match run_in_container(&container, vec!["tee", "main.java"], Duration::from_secs(2), true, Some(source)).await {
+    // Written without echoing it back, which would count against the output limit
+    match run_in_container(&container, vec!["sh", "-c", "cat > main.java"], Duration::from_secs(2), true, Some(source), None).await {
         Ok(CodeExec::Executed(Some(0), _, _)) => {},
         _ => {
             anyhow::bail!("Failed to create java sourcecode")
         }
     }
 
-    let ret = run_in_container(&container, vec!["java", "main.java"], time, false, stdin).await;
-    ret
+    let start = Instant::now();
+    let ret = run_in_container(&container, vec!["java", "main.java"], time, false, stdin, stderr_lines).await?;
+    let wall_time = start.elapsed();
+
+    // Stats can be gone after a timeout or OOM kill, which shouldn't cost the submission its verdict
+    let usage = container_usage(&container).await
+        .map_err(|e| warn!("Failed to read container usage: {}", e))
+        .ok()
+        .map(|usage| Usage {wall_ms: wall_time.as_millis() as u64, ..usage});
+    Ok((ret, usage))
 }
 
 #[cfg(test)]
@@ -24,11 +34,11 @@ mod test {
 
     #[tokio::test]
     async fn test_exec() {
-        let ret = exec_java_in_container("public class Main{public static void main(String[] args){System.out.println(\"yeet\");}}", "test:latest", Duration::from_secs(10), None).await.unwrap();
+        let (ret, _) = exec_java_in_container("public class Main{public static void main(String[] args){System.out.println(\"yeet\");}}", "test:latest", Duration::from_secs(10), None, None).await.unwrap();
 
-        assert!(matches!(ret, CodeExec::Executed(Some(0), s, _) if s == "yeet\n"));
+        assert!(matches!(ret, CodeExec::Executed(Some(0), s, _) if s == b"yeet\n"));
 
-        let ret = exec_java_in_container(
+        let (ret, _) = exec_java_in_container(
             "import java.io.File;
             import java.io.IOException;
             public class Main{public static void main(String[] args){
@@ -39,10 +49,10 @@ mod test {
                 }
             }
             }",
-            "test:latest", Duration::from_secs(10), None).await.unwrap();
+            "test:latest", Duration::from_secs(10), None, None).await.unwrap();
 
         dbg!(&ret);
 
-        assert!(matches!(ret, CodeExec::Executed(Some(0), s, _) if s == "yeet\n"));
+        assert!(matches!(ret, CodeExec::Executed(Some(0), s, _) if s == b"yeet\n"));
     }
 }
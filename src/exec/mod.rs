@@ -1,24 +1,75 @@
 use anyhow::Error;
 use tokio::process::Command;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 use tokio::time::timeout;
-use std::time::Duration;
-use std::str;
+use std::time::{Duration, Instant};
 use std::process::Stdio;
+use crate::config::CONFIG;
+use crate::metrics;
+use serde::{Deserialize, Serialize};
 
 pub mod python;
 pub mod docker;
 pub mod java;
 
+// Processes killed by a signal get 128 + the signal number, the same as docker reports
 type Status = Option<i32>;
+// Raw bytes a program wrote, which aren't necessarily valid UTF-8
+type Output = Vec<u8>;
+// Gets each line of a program's stderr as soon as it is written, without the newline
+pub type Lines = tokio::sync::mpsc::UnboundedSender<Vec<u8>>;
 
 
 // Expected outcomes of successful code execution
 // Ignores unexpected errors, such as being unable to start a process
 #[derive(Debug, Eq, PartialEq)]
 pub enum CodeExec {
-    Executed(Status, String, String),
+    Executed(Status, Output, Output),
     Timeout,
+    // Wrote more than `CONFIG.output_limit` bytes to stdout or stderr
+    OutputLimit,
+}
+
+// Resources used while running a submission
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Eq, PartialEq)]
+pub struct Usage {
+    pub wall_ms: u64,
+    pub user_ms: u64,
+    pub sys_ms: u64,
+    // Peak memory in bytes
+    pub peak_memory: u64,
+    pub oom_killed: bool,
+}
+
+enum ReadError {
+    LimitExceeded,
+    Io(std::io::Error),
+}
+
+// Read a child's output until EOF, bailing as soon as it goes over `limit` bytes
+// Completed lines are also sent to `lines` as they come in
+async fn read_limited(mut reader: impl AsyncRead + Unpin, limit: usize, lines: Option<&Lines>) -> Result<Vec<u8>, ReadError> {
+    let mut output = Vec::new();
+    let mut line_start = 0;
+    let mut buf = [0; 4096];
+
+    loop {
+        let n = reader.read(&mut buf).await.map_err(ReadError::Io)?;
+        if n == 0 {
+            return Ok(output)
+        }
+        if output.len() + n > limit {
+            return Err(ReadError::LimitExceeded)
+        }
+        output.extend_from_slice(&buf[..n]);
+
+        if let Some(lines) = lines {
+            while let Some(end) = output[line_start..].iter().position(|&b| b == b'\n') {
+                lines.send(output[line_start..line_start + end].to_vec()).ok();
+                line_start += end + 1;
+            }
+        }
+    }
 }
 
 async fn create_child<'a>(command: &str, args: impl IntoIterator<Item = &'a str>, stdin: Option<&str>) -> Result<tokio::process::Child, Error> {
@@ -43,25 +94,45 @@ async fn create_child<'a>(command: &str, args: impl IntoIterator<Item = &'a str>
 }
 
 
-pub async fn exec_timed<'a>(command: &str, args: impl IntoIterator<Item = &'a str>, time: Duration, stdin: Option<&str>)
+pub async fn exec_timed<'a>(command: &str, args: impl IntoIterator<Item = &'a str>, time: Duration, stdin: Option<&str>, stderr_lines: Option<&Lines>)
 -> Result<CodeExec, Error>
 {
-    let child = create_child(command, args, stdin).await?;
+    let start = Instant::now();
+    let mut child = create_child(command, args, stdin).await?;
+
+    // Close stdin so the child doesn't wait on more input
+    drop(child.stdin.take());
+    let stdout = read_limited(child.stdout.take().unwrap(), CONFIG.output_limit, None);
+    let stderr = read_limited(child.stderr.take().unwrap(), CONFIG.output_limit, stderr_lines);
 
-    let command = child.wait_with_output();
-    let timed_command = timeout(time, command);
-    let output = match timed_command.await {
+    // The child is killed on drop, so bailing early on a big output stops it
+    let output = async {
+        match futures::future::try_join(stdout, stderr).await {
+            Ok((stdout, stderr)) => Ok(Some((child.await?, stdout, stderr))),
+            Err(ReadError::LimitExceeded) => Ok(None),
+            Err(ReadError::Io(e)) => Err(e),
+        }
+    };
+    let timed_command = timeout(time, output);
+    let finished = timed_command.await;
+    metrics::EXEC_TIME.with_label_values(&[command]).observe(start.elapsed().as_secs_f64());
+    let (status, stdout, stderr) = match finished {
         Err(_) => {
+            metrics::EXEC_TIMEOUTS.with_label_values(&[command]).inc();
             return Ok(CodeExec::Timeout)
         },
-        Ok(v) => v?
+        Ok(v) => match v? {
+            Some(output) => output,
+            None => return Ok(CodeExec::OutputLimit),
+        }
+    };
+
+    let status = {
+        use std::os::unix::process::ExitStatusExt;
+        status.code().or_else(|| status.signal().map(|s| 128 + s))
     };
 
-    Ok(CodeExec::Executed(
-            output.status.code(),
-            str::from_utf8(&output.stdout)?.to_string(),
-            str::from_utf8(&output.stderr)?.to_string()
-        ))
+    Ok(CodeExec::Executed(status, stdout, stderr))
 }
 
 
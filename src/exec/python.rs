@@ -8,7 +8,7 @@ use super::{CodeExec, exec_timed};
 pub async fn exec_python<'a, I>(args: I, time: Duration, stdin: Option<&str>) -> Result<CodeExec, anyhow::Error>
 where I: IntoIterator<Item = &'a str>,
 {
-    exec_timed("python", args, time, stdin).await
+    exec_timed("python", args, time, stdin, None).await
 }
 
 #[cfg(test)]
@@ -20,7 +20,7 @@ mod tests{
         let success = exec_python(vec!["-c", "import sys; sys.exit()"], Duration::new(10, 0), None).await
         .expect("Something went wrong");
 
-        assert_eq!(success, CodeExec::Executed(Some(0), "".to_string(), "".to_string()));
+        assert_eq!(success, CodeExec::Executed(Some(0), Vec::new(), Vec::new()));
 
         let timeout = exec_python(vec!["-c", "import sys; sys.exit()"], Duration::new(0, 0), None).await
         .expect("Something went wrong");
@@ -30,7 +30,12 @@ mod tests{
         let bad_status = exec_python(vec!["-c", "import sys; sys.exit(1)"], Duration::new(10, 0), None).await
         .expect("Something went wrong");
 
-        assert_eq!(bad_status, CodeExec::Executed(Some(1), "".to_string(), String::new()));
+        assert_eq!(bad_status, CodeExec::Executed(Some(1), Vec::new(), Vec::new()));
+
+        let killed = exec_python(vec!["-c", "import os; os.kill(os.getpid(), 11)"], Duration::new(10, 0), None).await
+        .expect("Something went wrong");
+
+        assert_eq!(killed, CodeExec::Executed(Some(139), Vec::new(), Vec::new()));
 
         let inf_code = "while True:\n    pass
         ";
@@ -43,12 +48,32 @@ mod tests{
         let stdout = exec_python(vec!["-c", "print('yeet')"], Duration::new(10, 0), None).await
         .expect("Something went wrong");
 
-        assert_eq!(stdout, CodeExec::Executed(Some(0), "yeet\n".to_string(), String::new()));
+        assert_eq!(stdout, CodeExec::Executed(Some(0), b"yeet\n".to_vec(), Vec::new()));
 
         let stdin = exec_python(vec!["-c", "print(input())"], Duration::new(10, 0), Some("yeet")).await
         .expect("Something went wrong");
 
-        assert_eq!(stdin, CodeExec::Executed(Some(0), "yeet\n".to_string(), String::new()));
+        assert_eq!(stdin, CodeExec::Executed(Some(0), b"yeet\n".to_vec(), Vec::new()));
+
+        let spam = exec_python(vec!["-c", "while True:\n    print('x')"], Duration::new(10, 0), None).await
+        .expect("Something went wrong");
+
+        assert_eq!(spam, CodeExec::OutputLimit);
+
+        let binary = exec_python(vec!["-c", "import sys; sys.stdout.buffer.write(b'\\xff\\xfe')"], Duration::new(10, 0), None).await
+        .expect("Something went wrong");
+
+        assert_eq!(binary, CodeExec::Executed(Some(0), vec![0xff, 0xfe], Vec::new()));
+
+        let (lines, mut recv) = tokio::sync::mpsc::unbounded_channel();
+        let stderr = exec_timed("python", vec!["-c", "import sys; sys.stderr.write('a\\nb\\nc')"], Duration::new(10, 0), None, Some(&lines)).await
+        .expect("Something went wrong");
+        drop(lines);
+
+        assert_eq!(stderr, CodeExec::Executed(Some(0), Vec::new(), b"a\nb\nc".to_vec()));
+        assert_eq!(recv.recv().await, Some(b"a".to_vec()));
+        assert_eq!(recv.recv().await, Some(b"b".to_vec()));
+        assert_eq!(recv.recv().await, None);
     }
 
 }
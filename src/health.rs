@@ -0,0 +1,204 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, MutexGuard, Semaphore};
+use crate::config::{Language, LANGUAGES};
+use crate::exec::{CodeExec, exec_timed};
+use crate::exec::docker::{exec_in_container, exec_in_dangling_container};
+use crate::exec::java::exec_java_in_container;
+
+// How long each docker command in a check gets
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Commands that have to fail in the sandbox, or else code can get out of it
+const SANDBOX_CHECKS: &[(&str, &[&str])] = &[
+    ("no network", &["ping", "-c", "1", "-W", "1", "8.8.8.8"]),
+    ("read-only", &["touch", "test.py"]),
+];
+
+const JAVA_PROGRAM: &str = "public class Main { public static void main(String[] args) { System.out.println(\"ok\"); } }";
+
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    // Why it failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Check {
+    fn new(name: &str, result: Result<(), String>) -> Check {
+        Check {name: name.to_string(), ok: result.is_ok(), error: result.err()}
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: Vec<Check>,
+    // Sandbox checks that got out
+    pub escapes: Vec<String>,
+    // Seconds since the epoch
+    pub checked_at: i64,
+}
+
+// Run a docker command, failing with its output if it doesn't exit cleanly
+async fn docker(args: &[&str]) -> Result<(), String> {
+    match exec_timed("docker", args.iter().copied(), CHECK_TIMEOUT, None, None).await {
+        Ok(CodeExec::Executed(Some(0), _, _)) => Ok(()),
+        Ok(CodeExec::Executed(_, _, stderr)) => Err(String::from_utf8_lossy(&stderr).trim().to_string()),
+        Ok(e) => Err(format!("{:?}", e)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Run something that prints "ok" in the language's sandbox
+async fn run_program(language: Language) -> Result<(), String> {
+    let ran = match language {
+        Language::Python => exec_in_dangling_container(
+            language.image(), vec!["python", "-c", "print('ok')"], CHECK_TIMEOUT, false, None, true, None
+        ).await,
+        Language::Java => exec_java_in_container(JAVA_PROGRAM, language.image(), CHECK_TIMEOUT, None, None).await,
+    };
+    match ran {
+        Ok((CodeExec::Executed(Some(0), stdout, _), _)) if stdout == b"ok\n" => Ok(()),
+        Ok((e, _)) => Err(format!("Unexpected result {:?}", e)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Ok if the command failed like it should, and whether it escaped if it didn't
+async fn sandbox(image: &str, command: &[&str]) -> (Result<(), String>, bool) {
+    match exec_in_container(image, command.iter().copied(), CHECK_TIMEOUT, None, true).await {
+        Ok(CodeExec::Executed(Some(0), _, _)) => (Err("Escaped the sandbox".to_string()), true),
+        Ok(CodeExec::Executed(_, _, _)) => (Ok(()), false),
+        Ok(e) => (Err(format!("Unexpected result {:?}", e)), false),
+        Err(e) => (Err(e.to_string()), false),
+    }
+}
+
+// Whether docker is up, every image is there and every language can run code
+pub async fn check(now: i64) -> Readiness {
+    let docker_up = docker(&["version"]).await;
+    let up = docker_up.is_ok();
+    let mut checks = vec![Check::new("docker", docker_up)];
+    let mut escapes = Vec::new();
+    // Nothing else works without docker, and each check would just wait for its timeout
+    let unreachable = || Err("Docker isn't reachable.".to_string());
+
+    let images: BTreeSet<&str> = LANGUAGES.iter().map(|l| l.image()).collect();
+    for image in images {
+        let found = if up { docker(&["image", "inspect", image]).await } else { unreachable() };
+        checks.push(Check::new(&format!("image {}", image), found));
+
+        for (name, command) in SANDBOX_CHECKS {
+            let name = format!("{} in {}", name, image);
+            let (result, escaped) = if up { sandbox(image, command).await } else { (unreachable(), false) };
+            if escaped {
+                escapes.push(name.to_string());
+            }
+            checks.push(Check::new(&name, result));
+        }
+    }
+    for &language in &LANGUAGES {
+        let ran = if up { run_program(language).await } else { unreachable() };
+        checks.push(Check::new(language.name(), ran));
+    }
+
+    Readiness {ready: checks.iter().all(|c| c.ok), checks, escapes, checked_at: now}
+}
+
+// Keeps the last readiness check for `ttl`, since running code in every language is slow
+pub struct Probe {
+    // Only ever locked briefly, so probes never wait on a running check for it
+    last: std::sync::Mutex<Option<(Instant, Readiness)>>,
+    // Held while a check runs, so only one runs at a time
+    running: Mutex<()>,
+    ttl: Duration,
+}
+
+impl Probe {
+    pub fn new(ttl: Duration) -> Probe {
+        Probe {last: std::sync::Mutex::new(None), running: Mutex::new(()), ttl}
+    }
+
+    fn last(&self) -> Option<(Instant, Readiness)> {
+        self.last.lock().unwrap().clone()
+    }
+
+    // A check takes a permit from `bound`, the same as a submission, so it doesn't add to how many containers run
+    async fn run(&self, now: i64, bound: &Semaphore, _running: MutexGuard<'_, ()>) -> Readiness {
+        let _permit = bound.acquire().await;
+        let readiness = check(now).await;
+        *self.last.lock().unwrap() = Some((Instant::now(), readiness.clone()));
+        readiness
+    }
+
+    // Reuses the last check for `ttl`, and past that for as long as another check is running,
+    // so probes don't queue behind submissions for a permit. Only waits if there's never been a check
+    // Also returns whether it was checked just now, rather than reused
+    pub async fn check(&self, now: i64, bound: &Semaphore) -> (Readiness, bool) {
+        let last = self.last();
+        if let Some((at, readiness)) = &last {
+            if at.elapsed() < self.ttl {
+                return (readiness.clone(), false)
+            }
+        }
+        match (self.running.try_lock(), last) {
+            (Ok(running), _) => (self.run(now, bound, running).await, true),
+            (Err(_), Some((_, readiness))) => (readiness, false),
+            (Err(_), None) => {
+                let running = self.running.lock().await;
+                match self.last() {
+                    Some((_, readiness)) => (readiness, false),
+                    None => (self.run(now, bound, running).await, true),
+                }
+            },
+        }
+    }
+
+    // Checks again whether or not the last check is still fresh, for refreshing it before anyone has to wait
+    pub async fn refresh(&self, now: i64, bound: &Semaphore) -> Readiness {
+        let running = self.running.lock().await;
+        self.run(now, bound, running).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check() {
+        let readiness = check(100).await;
+        let names: Vec<&str> = readiness.checks.iter().map(|c| &c.name[..]).collect();
+        assert_eq!(names, vec!["docker", "image test", "no network in test", "read-only in test", "python", "java"]);
+        assert_eq!(readiness.ready, readiness.checks.iter().all(|c| c.ok));
+        assert!(readiness.checks.iter().all(|c| c.ok == c.error.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_probe() {
+        let probe = Probe::new(Duration::from_secs(60));
+        let bound = Semaphore::new(1);
+        let (first, fresh) = probe.check(100, &bound).await;
+        assert!(fresh);
+        // Cached, so it still says when it was first checked, and doesn't need a permit
+        let _taken = bound.acquire().await;
+        assert_eq!(probe.check(200, &bound).await, (first, false));
+
+        // Waits for a permit before checking again
+        let stale = Probe::new(Duration::from_secs(0));
+        assert!(tokio::time::timeout(Duration::from_millis(100), stale.check(300, &bound)).await.is_err());
+
+        // Serves the last check while another waits for a permit
+        drop(_taken);
+        let (last, _) = stale.check(400, &bound).await;
+        let _taken = bound.acquire().await;
+        let refresh = stale.refresh(500, &bound);
+        futures::pin_mut!(refresh);
+        assert!(tokio::time::timeout(Duration::from_millis(100), &mut refresh).await.is_err());
+        assert_eq!(tokio::time::timeout(Duration::from_millis(100), stale.check(600, &bound)).await.unwrap(), (last, false));
+    }
+}
@@ -1,3 +1,17 @@
 pub mod exec;
 pub mod test;
 pub mod config;
+pub mod queue;
+pub mod api;
+pub mod db;
+pub mod auth;
+pub mod contest;
+pub mod scoreboard;
+pub mod cache;
+pub mod similarity;
+pub mod metrics;
+pub mod health;
+pub mod logging;
+pub mod audit;
+pub mod questions;
+pub mod markdown;
@@ -0,0 +1,86 @@
+use chrono::{SecondsFormat, Utc};
+use serde_json::json;
+use std::io::Write;
+use crate::config::{CONFIG, LogFormat};
+use crate::queue::JobId;
+
+tokio::task_local! {
+    // The submission being judged, so everything logged on its behalf can be tied back to it
+    // Set with `SUBMISSION.scope` around the task that judges it
+    pub static SUBMISSION: JobId;
+}
+
+// The submission whose task this is being called from, if any
+pub fn submission() -> Option<JobId> {
+    SUBMISSION.try_with(|id| *id).ok()
+}
+
+// Submitted code as it should appear in the logs
+pub fn code(code: &str) -> String {
+    redact(code, CONFIG.logging.redact_code)
+}
+
+fn redact(code: &str, redact_code: bool) -> String {
+    if redact_code {
+        format!("<{} bytes redacted>", code.len())
+    } else {
+        code.to_string()
+    }
+}
+
+fn json_line(record: &log::Record, submission: Option<JobId>) -> String {
+    let mut line = json!({
+        "ts": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        "level": record.level().to_string(),
+        "target": record.target(),
+        "msg": record.args().to_string(),
+    });
+    if let Some(id) = submission {
+        line["submission"] = json!(id);
+    }
+    line.to_string()
+}
+
+// Log in the configured format, filtered by RUST_LOG like before
+pub fn init() {
+    let mut builder = pretty_env_logger::formatted_builder();
+    if let Ok(filters) = std::env::var("RUST_LOG") {
+        builder.parse_filters(&filters);
+    }
+    if CONFIG.logging.format == LogFormat::Json {
+        builder.format(|buf, record| writeln!(buf, "{}", json_line(record, submission())));
+    }
+    builder.init();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(redact("def f(): pass", true), "<13 bytes redacted>");
+        assert_eq!(redact("def f(): pass", false), "def f(): pass");
+    }
+
+    #[test]
+    fn test_json_line() {
+        let args = format_args!("Judged {}", "identity");
+        let record = log::Record::builder().args(args).level(log::Level::Info).target("executor").build();
+
+        let line: serde_json::Value = serde_json::from_str(&json_line(&record, Some(3))).unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["target"], "executor");
+        assert_eq!(line["msg"], "Judged identity");
+        assert_eq!(line["submission"], 3);
+
+        let line: serde_json::Value = serde_json::from_str(&json_line(&record, None)).unwrap();
+        assert!(line.get("submission").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_submission() {
+        assert_eq!(submission(), None);
+        assert_eq!(SUBMISSION.scope(7, async { submission() }).await, Some(7));
+    }
+}
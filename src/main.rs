@@ -1,10 +1,30 @@
-use executor::config::{CONFIG, verify_config};
+use executor::config::{CONFIG, Contest, Language, LANGUAGES, verify_config};
+use executor::contest::{self, Status};
+use executor::scoreboard::{self, Scoreboard, Scoring};
+use std::collections::HashMap;
+use executor::exec::docker::kill_all_containers;
+use executor::test::{Reply, Progress};
+use executor::test::Verdict;
+use executor::exec::Lines;
+use executor::config::Question;
+use executor::questions::{self, Questions};
+use std::sync::Arc;
+use executor::queue::{Queue, JobId, JobEvent};
+use executor::api::{ApiError, ErrorCode, Submitted, Boilerplate, QuestionSummary, QuestionStatus, QuestionDetail, ContestDetail, QuestionEdit, Preview, SolutionRun, Credentials, NewToken, Account, LoggedIn, CreatedToken, RunOptions, SimilarityOptions, handle_rejection};
+use executor::db::{Db, User, TokenKind, TestResult, NewSubmission};
+use executor::auth::{self, SESSION_COOKIE};
+use executor::cache::{self, ResultCache};
+use executor::similarity;
+use executor::metrics;
+use executor::health::{Probe, Readiness};
+use executor::logging;
+use executor::audit::{self, Audit, Action, Event};
 use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::str::from_utf8;
-use tokio::sync::{Mutex, Semaphore};
-use std::net::SocketAddr;
-use warp::{Filter, http::{Response, StatusCode}};
-use log::{info, trace};
+use tokio::sync::{Mutex, Semaphore, oneshot, mpsc};
+use warp::{Filter, http::StatusCode};
+use log::{info, trace, warn};
 use lazy_static::lazy_static;
 use std::fs::read_to_string;
 use handlebars::Handlebars;
@@ -12,18 +32,57 @@ use serde_json::json;
 
 lazy_static! {
     static ref TIMEOUT: Duration = Duration::from_millis(CONFIG.timeout as u64);
-    static ref STATIC_CONTENT: String = Handlebars::new()
-        .render_template(
-            &read_to_string(&CONFIG.template).expect("Failed to read template file"),
-            &json!({"questions": &CONFIG.questions})
-        ).expect("Failed to render template");
-    static ref CACHE: Mutex<lru::LruCache<SocketAddr, Instant>> = Mutex::new(lru::LruCache::new(100));
+    // Keyed by user id
+    static ref CACHE: Mutex<lru::LruCache<i64, Instant>> = Mutex::new(lru::LruCache::new(100));
+    static ref AUDIT: Audit = open_audit().expect("Failed to open audit log");
+    static ref READINESS: Probe = Probe::new(Duration::from_secs(CONFIG.readiness_ttl));
+    static ref RESULTS: ResultCache = ResultCache::new(CONFIG.cache.capacity, Duration::from_secs(CONFIG.cache.ttl));
     static ref BOUND: Semaphore = Semaphore::new(CONFIG.max_proc as usize);
+    static ref SHUTDOWN_TIMEOUT: Duration = Duration::from_millis(CONFIG.shutdown_timeout as u64);
+    static ref QUEUE: Queue = Queue::new(CONFIG.queue_size);
+    static ref DB: Db = open_db().expect("Failed to open database");
+    static ref QUESTIONS: Questions = Questions::new(
+        &CONFIG.questions,
+        DB.saved_questions().expect("Failed to load saved questions"),
+    );
+    // Pages are rendered per request, so they show questions as they are now
+    static ref TEMPLATES: Handlebars<'static> = {
+        let mut templates = Handlebars::new();
+        templates.set_strict_mode(true);
+        templates.register_template_file("index", &CONFIG.index_template)
+            .expect("Failed to read index template");
+        templates.register_template_file("question", &CONFIG.question_template)
+            .expect("Failed to read question template");
+        templates.register_template_file("scoreboard", &CONFIG.scoreboard_template)
+            .expect("Failed to read scoreboard template");
+        templates.register_template_file("admin", &CONFIG.admin_template)
+            .expect("Failed to read admin template");
+        templates
+    };
 }
 
+// Set once we get a shutdown signal so new submissions are turned away
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
 
 cfg_if::cfg_if! {
     if #[cfg(test)] {
+        fn open_db() -> anyhow::Result<Db> {
+            Db::memory()
+        }
+
+        // Tests sign up whatever the shipped config says
+        fn signup_enabled() -> bool {
+            true
+        }
+
+        // Tests shouldn't add to the real audit log
+        fn open_audit() -> anyhow::Result<Audit> {
+            let path = std::env::temp_dir().join(format!("code-quiz-audit-{}.log", std::process::id()));
+            std::fs::remove_file(&path).ok();
+            Audit::open(path, CONFIG.audit.max_bytes, CONFIG.audit.keep)
+        }
+
         lazy_static::lazy_static! {
             static ref COUNTER: Mutex<usize> = Mutex::new(0);
         }
@@ -42,16 +101,34 @@ cfg_if::cfg_if! {
             }
         }
 
-        async fn test_python_in_container(_code: &str, _func_name: &str, _test_case: &str, _time: Duration) -> String {
-            assert_max_proc();
-            String::new()
+        // The mocks accept everything, and echo back what they were asked to run
+        async fn test_python_in_container(code: &str, func_name: &str, _test_case: &str, _time: Duration, nonce: &str, stderr_lines: Option<&Lines>) -> Reply {
+            if let Some(lines) = stderr_lines {
+                // Only the line with the nonce counts
+                lines.send(b"#progress {\"event\": \"test\", \"test\": 1, \"total\": 1, \"passed\": false}".to_vec()).ok();
+                lines.send(format!("#progress {} {{\"event\": \"test\", \"test\": 1, \"total\": 1, \"passed\": true}}", nonce).into_bytes()).ok();
+            }
+            assert_max_proc().await;
+            Reply::new(Verdict::Accepted, &format!("python {}: {}", func_name, code), None)
         }
 
-        async fn test_java_in_container(_code: &str, _func_name: &str, _test_case: &str, _time: Duration) -> String {
-            assert_max_proc();
-            String::new()
+        async fn test_java_in_container(code: &str, _template: &str, func_call: &str, _test_case: &str, _time: Duration, _nonce: &str, _stderr_lines: Option<&Lines>) -> Reply {
+            assert_max_proc().await;
+            Reply::new(Verdict::Accepted, &format!("java {}: {}", func_call, code), None)
         }
     } else {
+        fn open_db() -> anyhow::Result<Db> {
+            Db::open(&CONFIG.database)
+        }
+
+        fn signup_enabled() -> bool {
+            CONFIG.auth.signup
+        }
+
+        fn open_audit() -> anyhow::Result<Audit> {
+            Audit::open(&CONFIG.audit.path, CONFIG.audit.max_bytes, CONFIG.audit.keep)
+        }
+
         use executor::test::test_python_in_container;
         use executor::test::test_java_in_container;
     }
@@ -61,163 +138,1486 @@ cfg_if::cfg_if! {
 
 
 
-// TODO: maybe want to reject duplicate requests that come in too quickly
-//       this probably means getting a database, which is annoying but ehh
-async fn run(addr:Option<SocketAddr>, language: String, identifier: String, code: bytes::Bytes) -> Result<impl warp::Reply, warp::Rejection> {
+// Run a submission's tests in a container
+// The harness' stderr is sent to `stderr_lines` as it runs, with progress tagged with `nonce`
+async fn judge(language: Language, question: &Question, code: &str, nonce: &str, stderr_lines: &Lines) -> Reply {
+    let resp = match language {
+        Language::Python => test_python_in_container(
+            code,
+            &question.function_name,
+            &question.python.test_case,
+            Duration::from_millis(question.time_limit(language) as u64),
+            nonce,
+            Some(stderr_lines)
+        ).await,
+        Language::Java => test_java_in_container(
+            code,
+            &CONFIG.java_test_file,
+            &question.java.func_call,
+            &question.java.test_case,
+            Duration::from_millis(question.time_limit(language) as u64),
+            nonce,
+            Some(stderr_lines)
+        ).await,
+    };
+    trace!(target: "Run", "Got {} code {}. Judged {:?}", language, logging::code(code), resp);
+    resp
+}
+
+// Queue a submission, and judge it in the background once a container slot frees up
+// Code that was judged recently gets the same verdict straight away, unless `fresh` is set
+// It's saved to the user's history once it's judged, and audited along with its id there
+// Returns None if the queue is full
+fn enqueue(ip: &Ip, user: &User, contest: Option<&'static Contest>, language: Language, question: Arc<Question>, code: String, fresh: bool)
+-> Option<(JobId, oneshot::Receiver<Reply>)>
+{
+    let id = QUEUE.push(user.id)?;
+    let (send, recv) = oneshot::channel();
+    let user_id = user.id;
+    let submitted_at = executor::db::now();
+    let key = cache::key(&question.handle, language, &code);
+    let handle = question.handle.to_string();
+    let (ip, username) = (ip.clone(), user.username.clone());
+    let save = move |code: &str, resp: &Reply, tests: &[TestResult]| {
+        metrics::SUBMISSIONS.with_label_values(&[language.name(), &handle, &format!("{:?}", resp.verdict)]).inc();
+        let submission = NewSubmission {
+            user_id,
+            contest: contest.map(|c| &c.handle[..]),
+            question: &handle,
+            language,
+            code,
+            reply: resp,
+            tests,
+            created_at: submitted_at,
+        };
+        let saved = DB.save_submission(&submission).map_err(|e| warn!("Failed to save submission {}: {}", id, e)).ok();
+        record_event(Event {at: submitted_at, user: Some(username.clone()), ip: ip.clone(), action: Action::Submitted {
+            submission: saved,
+            question: handle.clone(),
+            language,
+            contest: contest.map(|c| c.handle.to_string()),
+            fresh,
+        }});
+    };
+
+    if let Some((resp, tests)) = if fresh { None } else { RESULTS.get(&key) } {
+        info!("Reusing the verdict of identical code for submission {}", id);
+        metrics::CACHE_HITS.with_label_values(&[language.name(), &question.handle]).inc();
+        QUEUE.start(id);
+        save(&code, &resp, &tests);
+        QUEUE.finish(id, resp.clone());
+        send.send(resp).ok();
+        return Some((id, recv))
+    }
+
+    let queued_at = Instant::now();
+    metrics::QUEUED.inc();
+    info!("Queued submission {} from {} to {} in {}", id, user.username, question.handle, language);
+    tokio::spawn(logging::SUBMISSION.scope(id, async move {
+        // The semaphore is fair, so jobs start in the order they were queued
+        let _sema = BOUND.acquire().await;
+        let labels = [language.name(), &question.handle[..]];
+        QUEUE.start(id);
+        metrics::QUEUED.dec();
+        metrics::RUNNING.inc();
+        metrics::QUEUE_WAIT.with_label_values(&labels).observe(queued_at.elapsed().as_secs_f64());
+        let started_at = Instant::now();
+
+        let (stderr_lines, mut lines) = mpsc::unbounded_channel();
+        let nonce = executor::test::nonce();
+        let (code_ref, question_ref, nonce_ref) = (&code, &question, &nonce);
+        let judged = async move {
+            judge(language, question_ref, code_ref, nonce_ref, &stderr_lines).await
+            // Dropping `stderr_lines` here lets the loop below finish
+        };
+        let progress = async {
+            let mut tests = Vec::new();
+            while let Some(line) = lines.recv().await {
+                if let Some(p) = Progress::parse(&line, &nonce) {
+                    if let Progress::Test {test, total, passed} = p {
+                        tests.push(TestResult {test, total, passed});
+                    }
+                    QUEUE.progress(id, p);
+                }
+            }
+            tests
+        };
+        let (resp, tests) = futures::join!(judged, progress);
+        metrics::JUDGE_TIME.with_label_values(&labels).observe(started_at.elapsed().as_secs_f64());
+        metrics::RUNNING.dec();
+        info!("Judged {:?} in {:?}", resp.verdict, started_at.elapsed());
+
+        RESULTS.put(key, &question.handle, &resp, &tests);
+        save(&code, &resp, &tests);
+        QUEUE.finish(id, resp.clone());
+        send.send(resp).ok();
+    }));
+
+    Some((id, recv))
+}
+
+// Checks shared by everything that takes a submission
+// Returns the decoded code and question
+async fn accept_submission(ip: &Ip, user: &User, contest: Option<&Contest>, handle: &str, language: Language, options: &RunOptions, code: &bytes::Bytes)
+-> Result<(String, Arc<Question>), warp::Rejection>
+{
+    if SHUTTING_DOWN.load(Ordering::SeqCst) {
+        return Err(ApiError::reject(ErrorCode::ShuttingDown, "Server is shutting down. Try again later."))
+    }
+
+    if options.fresh && !user.is_admin() {
+        return Err(ApiError::reject(ErrorCode::Forbidden, "Only admins can skip the cache."))
+    }
+
+    if let Some(contest) = contest {
+        check_contest(user, contest, handle, language)?;
+    }
+
     {
         let mut unlocked_cache = CACHE.lock().await;
-        let addr = match addr {
-            Some(a) => a,
-            None => return Ok(Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body("Internal server error".to_owned())),
-        };
-        match unlocked_cache.get(&addr) {
+        match unlocked_cache.get(&user.id) {
             Some(t) => {
                 if t.elapsed() < *TIMEOUT {
-                    return Ok(Response::builder().status(StatusCode::OK).body(
-                        json!({"sucess": false, "msg": "Code ran too soon. Please wait a little."}).to_string()
-                    ))
+                    metrics::RATE_LIMITED.inc();
+                    record(ip, Some(&user.username), Action::RateLimited {question: handle.to_string(), language});
+                    return Err(ApiError::reject(ErrorCode::RateLimited, "Code ran too soon. Please wait a little."))
                  }
             },
             None => {}
         };
-        unlocked_cache.put(addr.to_owned(), Instant::now());
+        unlocked_cache.put(user.id, Instant::now());
     }
 
-    {
-        let _sema = BOUND.acquire().await;
-        let decoded_code = match from_utf8(&code) {
-            Ok(c) => c,
-            Err(_) => return Ok(Response::builder().status(StatusCode::BAD_REQUEST).body(String::new())),
-        };
+    let decoded_code = match from_utf8(code) {
+        Ok(c) => c.to_string(),
+        Err(_) => return Err(ApiError::reject(ErrorCode::InvalidEncoding, "Code must be valid UTF-8.")),
+    };
 
-        match (&CONFIG.questions.get(&identifier), &language[..]) {
-            (None, _) => Err(warp::reject::reject()),
-            (Some(q), "python") => {
-                let resp = test_python_in_container(
-                    decoded_code,
-                    &q.function_name,
-                    &q.python.test_case,
-                    *TIMEOUT
-                ).await;
-                trace!(target: "Run", "Got python code {}. Sent response {} to {}", decoded_code, resp, addr.unwrap());
-                Ok(Response::builder().body(resp))
-            },
-            (Some(q), "java") => {
-                let resp = test_java_in_container(
-                    decoded_code,
-                    &CONFIG.java_test_file,
-                    &q.java.func_call,
-                    &q.java.test_case,
-                    *TIMEOUT
-                ).await;
-                trace!(target: "Run", "Got java code {}. Sent response {} to {}", decoded_code, resp, addr.unwrap());
-                Ok(Response::builder().body(resp))
-            },
-            _ => Err(warp::reject::reject()),
+    let question = find_question(handle)?;
+    Ok((decoded_code, question))
+}
+
+// Whether the user can submit to a contest right now
+fn check_contest(user: &User, contest: &Contest, handle: &str, language: Language) -> Result<(), warp::Rejection> {
+    if !contest.questions.iter().any(|q| q == handle) {
+        return Err(ApiError::reject(ErrorCode::UnknownQuestion, "No such question in this contest."))
+    }
+    if !contest.languages.contains(&language) {
+        return Err(ApiError::reject(ErrorCode::LanguageNotAllowed, "That language isn't allowed in this contest."))
+    }
+
+    let started_at = DB.contest_started_at(&contest.handle, user.id).map_err(db_error)?;
+    let not_open = |msg| Err(ApiError::reject(ErrorCode::ContestNotOpen, msg));
+    match contest::status(contest, started_at, executor::db::now()) {
+        Status::Running {..} => Ok(()),
+        Status::Upcoming {..} => not_open("The contest hasn't started yet."),
+        Status::Ready {..} => not_open("Start the contest first."),
+        Status::Finished => not_open("The contest is over."),
+    }
+}
+
+fn find_question(handle: &str) -> Result<Arc<Question>, warp::Rejection> {
+    QUESTIONS.get(handle)
+        .ok_or_else(|| ApiError::reject(ErrorCode::UnknownQuestion, "No such question."))
+}
+
+fn queue_full() -> warp::Rejection {
+    ApiError::reject(ErrorCode::QueueFull, "Too many submissions queued. Try again later.")
+}
+
+// `contest` is None for practice
+async fn run(contest: Option<&'static Contest>, handle: String, language: Language, ip: Ip, user: User, options: RunOptions, code: bytes::Bytes)
+-> Result<impl warp::Reply, warp::Rejection>
+{
+    let (code, question) = accept_submission(&ip, &user, contest, &handle, language, &options, &code).await?;
+
+    let (_, result) = enqueue(&ip, &user, contest, language, question, code, options.fresh).ok_or_else(queue_full)?;
+    let resp = result.await.unwrap_or_else(|_| Reply::internal_error());
+    Ok(warp::reply::json(&resp))
+}
+
+// Like `run`, but replies with a job id straight away instead of waiting for the result
+async fn submit(contest: Option<&'static Contest>, handle: String, language: Language, ip: Ip, user: User, options: RunOptions, code: bytes::Bytes)
+-> Result<impl warp::Reply, warp::Rejection>
+{
+    let (code, question) = accept_submission(&ip, &user, contest, &handle, language, &options, &code).await?;
+
+    let (id, _) = enqueue(&ip, &user, contest, language, question, code, options.fresh).ok_or_else(queue_full)?;
+    Ok(warp::reply::with_status(warp::reply::json(&Submitted {id}), StatusCode::ACCEPTED))
+}
+
+fn no_such_submission() -> warp::Rejection {
+    ApiError::reject(ErrorCode::NotFound, "No such submission.")
+}
+
+// Only whoever submitted a job, or an admin, can watch it
+fn check_submission(id: JobId, user: &User) -> Result<(), warp::Rejection> {
+    match QUEUE.owner(id) {
+        Some(owner) if owner == user.id || user.is_admin() => Ok(()),
+        _ => Err(no_such_submission()),
+    }
+}
+
+async fn get_submission(id: JobId, user: User) -> Result<impl warp::Reply, warp::Rejection> {
+    check_submission(id, &user)?;
+    let status = QUEUE.status(id).ok_or_else(no_such_submission)?;
+    Ok(warp::reply::json(&status))
+}
+
+// Stream a submission's status and test progress as server sent events
+// Ends after the `finished` status
+async fn submission_events(id: JobId, user: User) -> Result<impl warp::Reply, warp::Rejection> {
+    use futures::{stream, StreamExt};
+
+    check_submission(id, &user)?;
+    let (status, events) = QUEUE.subscribe(id).ok_or_else(no_such_submission)?;
+
+    let events = stream::once(async { JobEvent::Status(status) })
+        // A slow client can miss events, but will still get the final result
+        .chain(events.into_stream().filter_map(|e| async { e.ok() }))
+        .map(|e| {
+            let name = match e {
+                JobEvent::Status(_) => "status",
+                JobEvent::Progress(_) => "progress",
+            };
+            Ok::<_, std::convert::Infallible>((warp::sse::event(name), warp::sse::json(e)))
+        });
+
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(events)))
+}
+
+// The user's own submissions to a question, newest first
+async fn question_history(handle: String, user: User) -> Result<impl warp::Reply, warp::Rejection> {
+    let question = find_question(&handle)?;
+    Ok(warp::reply::json(&DB.submissions(user.id, &question.handle).map_err(db_error)?))
+}
+
+// Anyone can see their own submissions, and admins can see everyone's
+async fn get_history(id: i64, ip: Ip, user: User) -> Result<impl warp::Reply, warp::Rejection> {
+    match DB.submission(id).map_err(db_error)? {
+        Some(s) if s.user_id == user.id => Ok(warp::reply::json(&s)),
+        Some(s) if user.is_admin() => {
+            record(&ip, Some(&user.username), Action::Admin {did: format!("viewed submission {}", id)});
+            Ok(warp::reply::json(&s))
+        },
+        _ => Err(ApiError::reject(ErrorCode::NotFound, "No such submission.")),
+    }
+}
+
+// Writes to the audit log, as `user` or as the server itself if None
+// Failing to shouldn't fail the request, but it should be noticed
+fn record(ip: &Ip, user: Option<&str>, action: Action) {
+    record_event(Event {at: executor::db::now(), user: user.map(str::to_string), ip: ip.clone(), action});
+}
+
+fn record_event(event: Event) {
+    if let Err(e) = AUDIT.record(&event) {
+        warn!("Failed to write to the audit log: {}", e);
+    }
+}
+
+// Admins only
+async fn get_audit(ip: Ip, user: User, query: audit::Query) -> Result<impl warp::Reply, warp::Rejection> {
+    require_admin(&user)?;
+    record(&ip, Some(&user.username), Action::Admin {did: "read the audit log".to_string()});
+    let events = blocking(move || {
+        AUDIT.flush();
+        AUDIT.query(&query)
+    }).await?.map_err(|e| {
+        warn!("Failed to read the audit log: {}", e);
+        internal_error()
+    })?;
+    Ok(warp::reply::json(&events))
+}
+
+fn require_admin(user: &User) -> Result<(), warp::Rejection> {
+    if user.is_admin() {
+        Ok(())
+    } else {
+        Err(ApiError::reject(ErrorCode::Forbidden, "Only admins can do that."))
+    }
+}
+
+// Pairs of people whose latest submissions to a question look copied from each other, as of the last batch check
+async fn get_similarity(handle: String, language: Language, ip: Ip, user: User, options: SimilarityOptions)
+-> Result<impl warp::Reply, warp::Rejection>
+{
+    require_admin(&user)?;
+    let question = find_question(&handle)?;
+    record(&ip, Some(&user.username), Action::Admin {did: format!("checked {} in {} for copying", question.handle, language)});
+    let contest = match options.contest {
+        Some(c) => Some(CONFIG.contests.get(&c).ok_or_else(|| ApiError::reject(ErrorCode::UnknownContest, "No such contest."))?),
+        None => None,
+    };
+    let threshold = options.threshold.unwrap_or(similarity::DEFAULT_THRESHOLD);
+
+    let mut report = DB.similarity_report(&question.handle, language, contest.map(|c| &c.handle[..])).map_err(db_error)?
+        .ok_or_else(|| ApiError::reject(ErrorCode::NotFound, "Not checked yet. Run `executor similarity` or wait for the next check."))?;
+    report.pairs.retain(|p| p.similarity >= threshold);
+    Ok(warp::reply::json(&report))
+}
+
+// Compares the latest submissions to every question in every language, overall and in each contest it's in,
+// and saves the reports for `get_similarity`
+// Every pair is compared, which takes a while with a lot of people, so this runs off the request path
+fn check_similarity() -> anyhow::Result<usize> {
+    let mut reports = 0;
+    for question in QUESTIONS.enabled() {
+        for &language in LANGUAGES.iter() {
+            let contests = CONFIG.contests.values()
+                .filter(|c| c.questions.contains(&question.handle) && c.languages.contains(&language))
+                .map(|c| Some(&c.handle[..]));
+            for contest in std::iter::once(None).chain(contests) {
+                let checked_at = executor::db::now();
+                let sources = DB.latest_submissions(&question.handle, language, contest)?;
+                let pairs = similarity::report(language, &sources, similarity::STORED_THRESHOLD);
+                DB.save_similarity_report(&question.handle, language, contest, &pairs, checked_at)?;
+                reports += 1;
+            }
         }
     }
+    Ok(reports)
 }
 
-fn run_filter() -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::post()
-        .and(warp::path("run"))
-        .and(warp::addr::remote())
-        .and(warp::path::param::<String>())
-        .and(warp::path::param::<String>())
-        .and(warp::body::content_length_limit(1024 * 64))
-        .and(warp::body::bytes())
-        .and_then(run)
-        .with(
-            // Probably want to make this not *
-            warp::reply::with::default_header("Access-Control-Allow-Origin", "*")
-        )
+// Reruns the similarity check every `similarity_interval` seconds
+async fn refresh_similarity() {
+    if CONFIG.similarity_interval == 0 {
+        return
+    }
+    loop {
+        let started_at = Instant::now();
+        match tokio::task::spawn_blocking(check_similarity).await {
+            Ok(Ok(reports)) => info!("Checked {} questions and languages for copying in {:?}", reports, started_at.elapsed()),
+            Ok(Err(e)) => warn!("Failed to check for copying: {}", e),
+            Err(e) => warn!("Similarity check panicked: {}", e),
+        }
+        tokio::time::delay_for(Duration::from_secs(CONFIG.similarity_interval)).await;
+    }
+}
+
+fn contest_detail(contest: &'static Contest, user: &User) -> Result<ContestDetail<'static>, warp::Rejection> {
+    let started_at = DB.contest_started_at(&contest.handle, user.id).map_err(db_error)?;
+    let submissions = DB.contest_submissions(&contest.handle, user.id).map_err(db_error)?;
+    Ok(ContestDetail {
+        handle: &contest.handle,
+        title: contest.title(),
+        questions: &contest.questions,
+        languages: &contest.languages,
+        start: contest.start.timestamp(),
+        end: contest.end.timestamp(),
+        duration: contest.duration,
+        now: executor::db::now(),
+        status: contest::status(contest, started_at, executor::db::now()),
+        score: contest::score(contest, &submissions, contest::deadline(contest, started_at)),
+    })
+}
+
+// Soonest first
+async fn list_contests(user: User) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut contests: Vec<&'static Contest> = CONFIG.contests.values().collect();
+    contests.sort_by_key(|c| (c.start, &c.handle));
+    let contests = contests.into_iter()
+        .map(|c| contest_detail(c, &user))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(warp::reply::json(&contests))
+}
+
+async fn get_contest(contest: &'static Contest, user: User) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&contest_detail(contest, &user)?))
+}
+
+// Start the participant's clock, for contests with a duration
+// Does nothing if it's already started, or the contest has no duration
+async fn start_contest(contest: &'static Contest, user: User) -> Result<impl warp::Reply, warp::Rejection> {
+    let started_at = DB.contest_started_at(&contest.handle, user.id).map_err(db_error)?;
+    let now = executor::db::now();
+    match contest::status(contest, started_at, now) {
+        Status::Ready {..} => {
+            DB.start_contest(&contest.handle, user.id, now).map_err(db_error)?;
+            info!("{} started contest {}", user.username, contest.handle);
+        },
+        Status::Running {..} => {},
+        Status::Upcoming {..} => return Err(ApiError::reject(ErrorCode::ContestNotOpen, "The contest hasn't started yet.")),
+        Status::Finished => return Err(ApiError::reject(ErrorCode::ContestNotOpen, "The contest is over.")),
+    }
+    Ok(warp::reply::json(&contest_detail(contest, &user)?))
+}
+
+// Contests without a public scoreboard are only shown to admins
+fn check_scoreboard(contest: &Contest, user: Option<&User>) -> Result<(), warp::Rejection> {
+    if contest.public_scoreboard || user.is_some_and(|u| u.is_admin()) {
+        Ok(())
+    } else {
+        Err(ApiError::reject(ErrorCode::Forbidden, "This scoreboard is private."))
+    }
+}
+
+// Rank a contest's participants, or everyone across every submission if `contest` is None
+fn make_scoreboard(contest: Option<&Contest>) -> Result<Scoreboard, warp::Rejection> {
+    let total = |handle: &str, language| QUESTIONS.get(handle).map_or(0, |q| q.test_count(language));
+    let entries = DB.scoreboard_entries(contest.map(|c| &c.handle[..]), total).map_err(db_error)?;
+
+    let contest = match contest {
+        Some(contest) => contest,
+        None => {
+            let questions: Vec<String> = QUESTIONS.enabled().iter().map(|q| q.handle.to_string()).collect();
+            return Ok(scoreboard::scoreboard(CONFIG.scoring, &questions, &entries, &HashMap::new(), |_| None))
+        },
+    };
+
+    let participants = DB.contest_participants(&contest.handle).map_err(db_error)?;
+    let started_at = |user_id| participants.get(&user_id).map(|(_, at)| *at);
+    // Scores are frozen at each participant's deadline
+    let entries: Vec<_> = entries.into_iter()
+        .filter(|e| contest::deadline(contest, started_at(e.user_id)).is_some_and(|d| e.submitted_at <= d))
+        .collect();
+    let names = participants.iter().map(|(id, (name, _))| (*id, name.to_string())).collect();
+    let start = |user_id| match contest.duration {
+        Some(_) => started_at(user_id),
+        None => Some(contest.start.timestamp()),
+    };
+    Ok(scoreboard::scoreboard(contest.scoring, &contest.questions, &entries, &names, start))
+}
+
+async fn get_scoreboard(contest: Option<&'static Contest>, user: Option<User>) -> Result<impl warp::Reply, warp::Rejection> {
+    if let Some(contest) = contest {
+        check_scoreboard(contest, user.as_ref())?;
+    }
+    Ok(warp::reply::json(&make_scoreboard(contest)?))
+}
+
+async fn scoreboard_page(contest: Option<&'static Contest>, user: Option<User>) -> Result<impl warp::Reply, warp::Rejection> {
+    if let Some(contest) = contest {
+        check_scoreboard(contest, user.as_ref())?;
+    }
+    let scoreboard = make_scoreboard(contest)?;
+    render_page("scoreboard", json!({
+        "title": contest.map_or("Practice", |c| c.title()),
+        "icpc": scoreboard.scoring == Scoring::Icpc,
+        "scoreboard": scoreboard,
+    }))
+}
+
+// Whether submissions can be judged right now
+// Doesn't bother checking once shutdown starts
+// Reuses the last check, which `refresh_readiness` keeps fresh
+async fn check_readiness() -> Readiness {
+    let (readiness, fresh) = READINESS.check(executor::db::now(), &BOUND).await;
+    if fresh {
+        report_escapes(&readiness);
+    }
+    readiness
+}
+
+fn report_escapes(readiness: &Readiness) {
+    for check in &readiness.escapes {
+        warn!("Sandbox check {} escaped", check);
+        record(&None, None, Action::SandboxEscape {check: check.to_string()});
+    }
+}
+
+// Checks again twice every `readiness_ttl`, so there's a fresh result before the last one expires,
+// even when a check has to wait for a container slot
+async fn refresh_readiness() {
+    loop {
+        report_escapes(&READINESS.refresh(executor::db::now(), &BOUND).await);
+        tokio::time::delay_for(Duration::from_secs(CONFIG.readiness_ttl) / 2).await;
+    }
+}
+
+async fn readiness() -> Result<impl warp::Reply, warp::Rejection> {
+    if SHUTTING_DOWN.load(Ordering::SeqCst) {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&json!({"ready": false, "checks": [], "checked_at": executor::db::now()})),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ))
+    }
+    let readiness = check_readiness().await;
+    let status = if readiness.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    Ok(warp::reply::with_status(warp::reply::json(&readiness), status))
+}
+
+async fn get_boilerplate(handle: String, language: Language) -> Result<impl warp::Reply, warp::Rejection> {
+    let question = find_question(&handle)?;
+    Ok(warp::reply::json(&Boilerplate {handle: &question.handle, language, boilerplate: question.boilerplate(language)}))
+}
+
+async fn list_questions() -> Result<impl warp::Reply, warp::Rejection> {
+    let questions = QUESTIONS.enabled();
+    let questions: Vec<QuestionSummary> = questions.iter().map(|q| QuestionSummary::from(&**q)).collect();
+    Ok(warp::reply::json(&questions))
 }
 
-async fn get_boilerplate(q: String, s: String) -> Result<impl warp::Reply, warp::Rejection> {
-    CONFIG.questions.get(&q).map_or(
-        Err(warp::reject::reject()),
-        |q|{
-            match &s[..] {
-                "python" => Ok(q.python.boilerplate.to_owned()),
-                "java" => Ok(q.java.boilerplate.to_owned()),
-                _ => Err(warp::reject::reject()),
+async fn get_question(handle: String) -> Result<impl warp::Reply, warp::Rejection> {
+    let question = find_question(&handle)?;
+    Ok(warp::reply::json(&QuestionDetail::from(&*question)))
+}
+
+// Run a question's reference solutions, in every language that has one
+// They wait for a container slot like any submission, but skip the queue and the cache
+async fn run_solutions(question: &Question) -> Vec<SolutionRun> {
+    let runs = LANGUAGES.iter().map(|&language| async move {
+        let code = question.solution(language);
+        if code.trim().is_empty() {
+            return SolutionRun {language, reply: None}
+        }
+        let _sema = BOUND.acquire().await;
+        // Nobody's watching its progress
+        let (lines, _) = mpsc::unbounded_channel();
+        SolutionRun {language, reply: Some(judge(language, question, code, &executor::test::nonce(), &lines).await)}
+    });
+    futures::future::join_all(runs).await
+}
+
+// Refuses questions that can't be saved, or can't be published if `enabled`
+async fn check_question(question: &Question, enabled: bool) -> Result<(), warp::Rejection> {
+    let problems = if enabled { questions::publish_problems(question) } else { questions::problems(question) };
+    if !problems.is_empty() {
+        return Err(ApiError::reject(ErrorCode::InvalidQuestion, &problems.join(" ")))
+    }
+    if enabled {
+        for run in run_solutions(question).await {
+            if let Some(reply) = run.reply.filter(|r| r.verdict != Verdict::Accepted) {
+                let message = format!("The {} reference solution got {:?}: {}", run.language, reply.verdict, reply.msg);
+                return Err(ApiError::reject(ErrorCode::InvalidQuestion, &message))
             }
         }
-    )
+    }
+    Ok(())
+}
+
+// Save it for good, and start serving it
+fn store_question(question: Question, enabled: bool) -> Result<(), warp::Rejection> {
+    DB.save_question(&question, enabled, executor::db::now()).map_err(db_error)?;
+    // Verdicts from before its tests changed can't be trusted
+    RESULTS.forget(&question.handle);
+    QUESTIONS.save(question, enabled);
+    Ok(())
+}
+
+fn find_any_question(handle: &str) -> Result<questions::Entry, warp::Rejection> {
+    QUESTIONS.entry(handle).ok_or_else(|| ApiError::reject(ErrorCode::UnknownQuestion, "No such question."))
+}
+
+// Every question including disabled ones, with their tests and reference solutions
+// The rest of the admin routes below are admins only too
+async fn list_all_questions(user: User) -> Result<impl warp::Reply, warp::Rejection> {
+    require_admin(&user)?;
+    Ok(warp::reply::json(&QUESTIONS.entries()))
+}
+
+async fn get_any_question(handle: String, user: User) -> Result<impl warp::Reply, warp::Rejection> {
+    require_admin(&user)?;
+    Ok(warp::reply::json(&find_any_question(&handle)?))
+}
+
+async fn create_question(ip: Ip, user: User, edit: QuestionEdit) -> Result<impl warp::Reply, warp::Rejection> {
+    require_admin(&user)?;
+    let handle = edit.question.handle.to_string();
+    if QUESTIONS.entry(&handle).is_some() {
+        return Err(ApiError::reject(ErrorCode::HandleTaken, "There's already a question with that handle."))
+    }
+    check_question(&edit.question, edit.enabled).await?;
+    store_question(edit.question, edit.enabled)?;
+    record(&ip, Some(&user.username), Action::Admin {did: format!("created question {}", handle)});
+    Ok(warp::reply::with_status(warp::reply::json(&find_any_question(&handle)?), StatusCode::CREATED))
+}
+
+// Questions from config.toml can be edited too, which replaces them until they're deleted
+async fn edit_question(handle: String, ip: Ip, user: User, mut edit: QuestionEdit) -> Result<impl warp::Reply, warp::Rejection> {
+    require_admin(&user)?;
+    find_any_question(&handle)?;
+    edit.question.handle = handle.to_string();
+    check_question(&edit.question, edit.enabled).await?;
+    store_question(edit.question, edit.enabled)?;
+    record(&ip, Some(&user.username), Action::Admin {did: format!("edited question {}", handle)});
+    Ok(warp::reply::json(&find_any_question(&handle)?))
+}
+
+async fn enable_question(handle: String, enabled: bool, ip: Ip, user: User) -> Result<impl warp::Reply, warp::Rejection> {
+    require_admin(&user)?;
+    let question = find_any_question(&handle)?.question;
+    if enabled {
+        check_question(&question, true).await?;
+    }
+    store_question(question, enabled)?;
+    let did = format!("{} question {}", if enabled { "enabled" } else { "disabled" }, handle);
+    record(&ip, Some(&user.username), Action::Admin {did});
+    Ok(warp::reply::json(&find_any_question(&handle)?))
+}
+
+// Past submissions to it are kept
+async fn delete_question(handle: String, ip: Ip, user: User) -> Result<impl warp::Reply, warp::Rejection> {
+    require_admin(&user)?;
+    find_any_question(&handle)?;
+    if QUESTIONS.in_config(&handle) {
+        return Err(ApiError::reject(ErrorCode::QuestionInUse, "Questions in config.toml can only be disabled."))
+    }
+    if CONFIG.contests.values().any(|c| c.questions.contains(&handle)) {
+        return Err(ApiError::reject(ErrorCode::QuestionInUse, "A contest uses this question."))
+    }
+    DB.delete_question(&handle).map_err(db_error)?;
+    RESULTS.forget(&handle);
+    QUESTIONS.remove(&handle);
+    record(&ip, Some(&user.username), Action::Admin {did: format!("deleted question {}", handle)});
+    Ok(warp::reply::with_status(warp::reply(), StatusCode::NO_CONTENT))
+}
+
+// Shows a question as it would be served and runs its reference solutions, without saving anything
+async fn preview_question(user: User, edit: QuestionEdit) -> Result<impl warp::Reply, warp::Rejection> {
+    require_admin(&user)?;
+    let question = edit.question;
+    // Solutions can't be run without tests to run them against
+    let solutions = if questions::problems(&question).is_empty() { run_solutions(&question).await } else { Vec::new() };
+    let problems = questions::publish_problems(&question);
+    Ok(warp::reply::json(&Preview {question: QuestionDetail::from(&question), problems, solutions}))
+}
+
+// Every enabled question, with what the user has solved if they're logged in
+async fn index_page(user: Option<User>) -> Result<impl warp::Reply, warp::Rejection> {
+    let attempted = match &user {
+        Some(user) => DB.attempted_questions(user.id).map_err(db_error)?,
+        None => HashMap::new(),
+    };
+    let questions = QUESTIONS.enabled();
+    let questions: Vec<QuestionStatus> = questions.iter().map(|q| QuestionStatus {
+        summary: QuestionSummary::from(&**q),
+        attempted: attempted.contains_key(&q.handle),
+        solved: attempted.get(&q.handle).copied().unwrap_or(false),
+    }).collect();
+    render_page("index", json!({"questions": questions, "user": user.map(|u| u.username)}))
+}
+
+async fn question_page(handle: String) -> Result<impl warp::Reply, warp::Rejection> {
+    let question = find_question(&handle)?;
+    render_page("question", json!({"question": QuestionDetail::from(&*question)}))
+}
+
+async fn admin_page() -> Result<impl warp::Reply, warp::Rejection> {
+    render_page("admin", json!({}))
+}
+
+// Every page also gets `api_url`, for its scripts
+fn render_page(template: &str, mut data: serde_json::Value) -> Result<impl warp::Reply, warp::Rejection> {
+    data["api_url"] = json!(CONFIG.api_url);
+    let page = TEMPLATES.render(template, &data).map_err(|e| {
+        warn!("Failed to render {}: {}", template, e);
+        internal_error()
+    })?;
+    Ok(warp::reply::html(page))
+}
+
+fn internal_error() -> warp::Rejection {
+    ApiError::reject(ErrorCode::Internal, "Internal server error.")
+}
+
+// The client doesn't need to know what went wrong
+fn db_error(e: anyhow::Error) -> warp::Rejection {
+    warn!("Database error: {}", e);
+    internal_error()
+}
+
+fn unauthorized() -> warp::Rejection {
+    ApiError::reject(ErrorCode::Unauthorized, "Log in first.")
+}
+
+// Password hashing is slow on purpose, so keep it off the threads serving requests
+async fn blocking<T, F>(f: F) -> Result<T, warp::Rejection>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await.map_err(|_| internal_error())
+}
+
+async fn sign_up(credentials: Credentials) -> Result<impl warp::Reply, warp::Rejection> {
+    if !signup_enabled() {
+        return Err(ApiError::reject(ErrorCode::Forbidden, "Signing up is disabled."))
+    }
+    let problem = auth::check_username(&credentials.username)
+        .or_else(|| auth::check_password(&credentials.password));
+    if let Some(problem) = problem {
+        return Err(ApiError::reject(ErrorCode::BadRequest, problem))
+    }
+    // They'd be made admins on the next start
+    if CONFIG.auth.admins.contains(&credentials.username) {
+        return Err(ApiError::reject(ErrorCode::UsernameTaken, "That username is reserved."))
+    }
+
+    let password = credentials.password;
+    let hash = blocking(move || auth::hash_password(&password)).await?.map_err(db_error)?;
+    let user = DB.create_user(&credentials.username, &hash).map_err(db_error)?
+        .ok_or_else(|| ApiError::reject(ErrorCode::UsernameTaken, "That username is taken."))?;
+    Ok(warp::reply::with_status(warp::reply::json(&Account::from(&user)), StatusCode::CREATED))
+}
+
+async fn log_in(ip: Ip, credentials: Credentials) -> Result<impl warp::Reply, warp::Rejection> {
+    let wrong = || {
+        record(&ip, Some(&credentials.username), Action::LoginFailed);
+        ApiError::reject(ErrorCode::Unauthorized, "Wrong username or password.")
+    };
+
+    let found = DB.user_by_name(&credentials.username).map_err(db_error)?;
+    // Missing users are checked against a dummy hash, so timing doesn't give away which usernames exist
+    let hash = found.as_ref().map_or_else(|| auth::DUMMY_HASH.clone(), |(_, hash)| hash.clone());
+    let password = credentials.password.clone();
+    let verified = blocking(move || auth::verify_password(&hash, &password)).await?;
+    let user = match found {
+        Some((user, _)) if verified => user,
+        _ => return Err(wrong()),
+    };
+    record(&ip, Some(&user.username), Action::LoggedIn);
+
+    // As good a time as any to clean up
+    DB.delete_expired_tokens().map_err(db_error)?;
+
+    let (token, token_hash) = auth::new_token();
+    let ttl = CONFIG.auth.session_ttl;
+    DB.create_token(user.id, &token_hash, TokenKind::Session, "login", Some(executor::db::now() + ttl as i64))
+        .map_err(db_error)?;
+    info!("{} logged in", user.username);
+
+    Ok(warp::reply::with_header(
+        warp::reply::json(&LoggedIn {token: &token, user: Account::from(&user)}),
+        "set-cookie",
+        auth::session_cookie(&token, ttl, CONFIG.auth.secure_cookie),
+    ))
+}
+
+// Logging out with an api token deletes it
+async fn log_out(token: String) -> Result<impl warp::Reply, warp::Rejection> {
+    DB.delete_token(&auth::hash_token(&token)).map_err(db_error)?;
+    Ok(warp::reply::with_header(
+        warp::reply::with_status(warp::reply(), StatusCode::NO_CONTENT),
+        "set-cookie",
+        auth::session_cookie("", 0, CONFIG.auth.secure_cookie),
+    ))
+}
+
+async fn get_account(user: User) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&Account::from(&user)))
+}
+
+async fn list_tokens(user: User) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&DB.api_tokens(user.id).map_err(db_error)?))
+}
+
+async fn create_token(user: User, new: NewToken) -> Result<impl warp::Reply, warp::Rejection> {
+    let (token, token_hash) = auth::new_token();
+    let info = DB.create_token(user.id, &token_hash, TokenKind::Api, &new.name, None).map_err(db_error)?;
+    Ok(warp::reply::with_status(warp::reply::json(&CreatedToken {token: &token, info}), StatusCode::CREATED))
+}
+
+async fn delete_token(id: i64, user: User) -> Result<impl warp::Reply, warp::Rejection> {
+    if !DB.delete_api_token(user.id, id).map_err(db_error)? {
+        return Err(ApiError::reject(ErrorCode::NotFound, "No such token."))
+    }
+    Ok(warp::reply::with_status(warp::reply(), StatusCode::NO_CONTENT))
+}
+
+// Path segments shared by the routes below
+
+fn handle() -> impl warp::Filter<Extract = (String,), Error = warp::Rejection> + Copy {
+    warp::path::param::<String>()
+}
+
+// Unlike a plain param, an unknown language gets its own error instead of a 404 for the whole path
+fn language() -> impl warp::Filter<Extract = (Language,), Error = warp::Rejection> + Copy {
+    warp::path::param::<String>()
+        .and_then(|language: String| async move {
+            language.parse::<Language>()
+                .map_err(|_| ApiError::reject(ErrorCode::UnknownLanguage, "Unsupported language."))
+        })
+}
+
+fn contest() -> impl warp::Filter<Extract = (&'static Contest,), Error = warp::Rejection> + Copy {
+    warp::path::param::<String>()
+        .and_then(|handle: String| async move {
+            CONFIG.contests.get(&handle)
+                .ok_or_else(|| ApiError::reject(ErrorCode::UnknownContest, "No such contest."))
+        })
+}
+
+fn job_id() -> impl warp::Filter<Extract = (JobId,), Error = warp::Rejection> + Copy {
+    warp::path::param::<JobId>()
+}
+
+fn code() -> impl warp::Filter<Extract = (bytes::Bytes,), Error = warp::Rejection> + Copy {
+    warp::body::content_length_limit(1024 * 64).and(warp::body::bytes())
+}
+
+// Where the request came from
+type Ip = Option<String>;
+
+fn remote() -> impl warp::Filter<Extract = (Ip,), Error = std::convert::Infallible> + Copy {
+    warp::addr::remote().map(|addr: Option<std::net::SocketAddr>| addr.map(|a| a.ip().to_string()))
+}
+
+fn json_body<T: serde::de::DeserializeOwned + Send>() -> impl warp::Filter<Extract = (T,), Error = warp::Rejection> + Copy {
+    warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+}
+
+// Questions can be a lot longer than anything else sent as json
+fn question_body() -> impl warp::Filter<Extract = (QuestionEdit,), Error = warp::Rejection> + Copy {
+    warp::body::content_length_limit(1024 * 256).and(warp::body::json())
+}
+
+// The token a request was made with, from an `Authorization: Bearer` header or the session cookie
+fn maybe_token() -> impl warp::Filter<Extract = (Option<String>,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(warp::cookie::optional(SESSION_COOKIE))
+        .map(|authorization: Option<String>, cookie: Option<String>| {
+            auth::find_token(authorization.as_deref(), cookie.as_deref())
+        })
+}
+
+fn token() -> impl warp::Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+    maybe_token().and_then(|token: Option<String>| async move { token.ok_or_else(unauthorized) })
+}
+
+// Like `user`, for routes anyone can use
+fn maybe_user() -> impl warp::Filter<Extract = (Option<User>,), Error = warp::Rejection> + Clone {
+    maybe_token().and_then(|token: Option<String>| async move {
+        match token {
+            Some(token) => DB.user_by_token(&auth::hash_token(&token)).map_err(db_error),
+            None => Ok(None),
+        }
+    })
+}
+
+// Who a request was made by, rejecting anyone who isn't logged in
+fn user() -> impl warp::Filter<Extract = (User,), Error = warp::Rejection> + Clone {
+    token().and_then(|token: String| async move {
+        DB.user_by_token(&auth::hash_token(&token)).map_err(db_error)?.ok_or_else(unauthorized)
+    })
+}
+
+// Every route under /api/v1
+// Paths always name the question before the language
+// Each path is matched before its method, so a wrong method on a real path is a 405 and not a 404
+fn api_filter() -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    use warp::path::end;
+
+    // POST /users
+    let sign_up = warp::path("users").and(end())
+        .and(warp::post()).and(json_body())
+        .and_then(sign_up);
+
+    // POST /login
+    let log_in = warp::path("login").and(end())
+        .and(warp::post()).and(remote()).and(json_body())
+        .and_then(log_in);
+
+    // POST /logout
+    let log_out = warp::path("logout").and(end())
+        .and(warp::post()).and(token())
+        .and_then(log_out);
+
+    // GET /account
+    let account = warp::path("account").and(end())
+        .and(warp::get()).and(user())
+        .and_then(get_account);
+
+    // GET /tokens
+    let tokens = warp::path("tokens").and(end())
+        .and(warp::get()).and(user())
+        .and_then(list_tokens);
+
+    // POST /tokens
+    let new_token = warp::path("tokens").and(end())
+        .and(warp::post()).and(user()).and(json_body())
+        .and_then(create_token);
+
+    // DELETE /tokens/{id}
+    let delete_token = warp::path("tokens").and(warp::path::param::<i64>()).and(end())
+        .and(warp::delete()).and(user())
+        .and_then(delete_token);
+
+    let practice = || warp::any().map(|| None);
+    let in_contest = || warp::path("contests").and(contest()).map(Some);
+
+    // POST /run/{handle}/{language}?fresh=true
+    // POST /contests/{contest}/run/{handle}/{language}
+    let run = in_contest().or(practice()).unify()
+        .and(warp::path("run")).and(handle()).and(language()).and(end())
+        .and(warp::post()).and(remote()).and(user()).and(warp::query::<RunOptions>()).and(code())
+        .and_then(run);
+
+    // POST /submissions/{handle}/{language}
+    // POST /contests/{contest}/submissions/{handle}/{language}
+    let submit = in_contest().or(practice()).unify()
+        .and(warp::path("submissions")).and(handle()).and(language()).and(end())
+        .and(warp::post()).and(remote()).and(user()).and(warp::query::<RunOptions>()).and(code())
+        .and_then(submit);
+
+    // GET /scoreboard
+    // GET /contests/{contest}/scoreboard
+    let scoreboard = in_contest().or(practice()).unify()
+        .and(warp::path("scoreboard")).and(end())
+        .and(warp::get()).and(maybe_user())
+        .and_then(get_scoreboard);
+
+    // GET /contests
+    let contests = warp::path("contests").and(end())
+        .and(warp::get()).and(user())
+        .and_then(list_contests);
+
+    // GET /contests/{contest}
+    let contest_info = warp::path("contests").and(contest()).and(end())
+        .and(warp::get()).and(user())
+        .and_then(get_contest);
+
+    // POST /contests/{contest}/start
+    let start_contest = warp::path("contests").and(contest()).and(warp::path("start")).and(end())
+        .and(warp::post()).and(user())
+        .and_then(start_contest);
+
+    // GET /submissions/{id}
+    let status = warp::path("submissions").and(job_id()).and(end())
+        .and(warp::get()).and(user())
+        .and_then(get_submission);
+
+    // GET /submissions/{id}/events
+    let events = warp::path("submissions").and(job_id()).and(warp::path("events")).and(end())
+        .and(warp::get()).and(user())
+        .and_then(submission_events);
+
+    // GET /questions/{handle}/history
+    let question_history = warp::path("questions").and(handle()).and(warp::path("history")).and(end())
+        .and(warp::get()).and(user())
+        .and_then(question_history);
+
+    // GET /history/{id}
+    let history = warp::path("history").and(warp::path::param::<i64>()).and(end())
+        .and(warp::get()).and(remote()).and(user())
+        .and_then(get_history);
+
+    // GET /similarity/{handle}/{language}?contest={contest}&threshold={threshold}
+    let similarity = warp::path("similarity").and(handle()).and(language()).and(end())
+        .and(warp::get()).and(remote()).and(user()).and(warp::query::<SimilarityOptions>())
+        .and_then(get_similarity);
+
+    // GET /audit?user={username}&action={action}&since={time}&limit={limit}
+    let audit = warp::path("audit").and(end())
+        .and(warp::get()).and(remote()).and(user()).and(warp::query::<audit::Query>())
+        .and_then(get_audit);
+
+    // GET /boilerplate/{handle}/{language}
+    let boilerplate = warp::path("boilerplate").and(handle()).and(language()).and(end())
+        .and(warp::get())
+        .and_then(get_boilerplate);
+
+    // GET /questions
+    let questions = warp::path("questions").and(end())
+        .and(warp::get())
+        .and_then(list_questions);
+
+    // GET /questions/{handle}
+    let question = warp::path("questions").and(handle()).and(end())
+        .and(warp::get())
+        .and_then(get_question);
+
+    warp::path("api")
+        .and(warp::path("v1"))
+        .and(
+            sign_up.or(log_in).or(log_out).or(account).or(tokens).or(new_token).or(delete_token)
+                .or(run).or(submit).or(status).or(events).or(question_history).or(history).or(similarity).or(audit)
+                .or(contests).or(contest_info).or(start_contest).or(scoreboard)
+                .or(boilerplate).or(questions).or(question)
+                .or(admin_filter())
+                .with(cors())
+        )
+}
+
+// Routes for managing questions, under /api/v1 like the rest
+fn admin_filter() -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    use warp::path::end;
+
+    // GET /admin/questions
+    // POST /admin/questions
+    let admin_questions = || warp::path("admin").and(warp::path("questions"));
+    let all_questions = admin_questions().and(end())
+        .and(warp::get()).and(user())
+        .and_then(list_all_questions);
+    let create_question = admin_questions().and(end())
+        .and(warp::post()).and(remote()).and(user()).and(question_body())
+        .and_then(create_question);
+
+    // POST /admin/questions/preview
+    let preview_question = admin_questions().and(warp::path("preview")).and(end())
+        .and(warp::post()).and(user()).and(question_body())
+        .and_then(preview_question);
+
+    // GET /admin/questions/{handle}
+    // POST /admin/questions/{handle}
+    // DELETE /admin/questions/{handle}
+    let admin_question = || admin_questions().and(handle());
+    let any_question = admin_question().and(end())
+        .and(warp::get()).and(user())
+        .and_then(get_any_question);
+    let edit_question = admin_question().and(end())
+        .and(warp::post()).and(remote()).and(user()).and(question_body())
+        .and_then(edit_question);
+    let delete_question = admin_question().and(end())
+        .and(warp::delete()).and(remote()).and(user())
+        .and_then(delete_question);
+
+    // POST /admin/questions/{handle}/enable
+    // POST /admin/questions/{handle}/disable
+    let enable_question = admin_question()
+        .and(warp::path("enable").map(|| true).or(warp::path("disable").map(|| false)).unify()).and(end())
+        .and(warp::post()).and(remote()).and(user())
+        .and_then(enable_question);
+
+    all_questions.or(create_question).or(preview_question)
+        .or(any_question).or(edit_question).or(delete_question).or(enable_question)
+}
+
+// Answers preflight requests, and rejects requests from origins that aren't in the config
+fn cors() -> warp::cors::Builder {
+    let config = &CONFIG.cors;
+    let cors = warp::cors()
+        .allow_methods(config.methods.iter().map(|m| &m[..]))
+        .allow_headers(config.headers.iter());
+    let cors = if config.origins.iter().any(|o| o == "*") {
+        cors.allow_any_origin()
+    } else {
+        // Panics if an origin isn't valid, which happens on startup
+        cors.allow_origins(config.origins.iter().map(|o| &o[..]))
+    };
+    match config.max_age {
+        Some(max_age) => cors.max_age(max_age),
+        None => cors,
+    }
+}
+
+fn routes() -> impl warp::Filter<Extract = impl warp::Reply, Error = std::convert::Infallible> + Clone {
+    // GET /
+    let index = warp::path::end()
+        .and(warp::get()).and(maybe_user())
+        .and_then(index_page);
+
+    // GET /questions/{handle}
+    let question = warp::path("questions").and(handle()).and(warp::path::end())
+        .and(warp::get())
+        .and_then(question_page);
+
+    // GET /admin
+    let admin = warp::path("admin").and(warp::path::end())
+        .and(warp::get())
+        .and_then(admin_page);
+
+    // GET /scoreboard
+    // GET /scoreboard/{contest}
+    let scoreboard = warp::path("scoreboard")
+        .and(contest().map(Some).or(warp::any().map(|| None)).unify())
+        .and(warp::path::end())
+        .and(warp::get()).and(maybe_user())
+        .and_then(scoreboard_page);
+
+    // GET /question-assets/{handle}/{file}
+    let assets = warp::path(&executor::markdown::ASSETS_PATH[1..])
+        .and(warp::get())
+        .and(warp::fs::dir(&CONFIG.question_assets[..]));
+
+    // GET /static/{file}
+    let files = warp::path("static")
+        .and(warp::get())
+        .and(warp::fs::dir(&CONFIG.static_dir[..]));
+
+    // GET /metrics
+    let metrics = warp::path("metrics").and(warp::path::end())
+        .and(warp::get())
+        .map(|| warp::reply::with_header(metrics::render(), "content-type", "text/plain; version=0.0.4"));
+
+    // GET /healthz
+    let health = warp::path("healthz").and(warp::path::end())
+        .and(warp::get())
+        .map(|| "ok");
+
+    // GET /readyz
+    let ready = warp::path("readyz").and(warp::path::end())
+        .and(warp::get())
+        .and_then(readiness);
+
+    api_filter().or(index).or(question).or(admin).or(assets).or(files).or(scoreboard).or(metrics).or(health).or(ready).recover(handle_rejection)
+}
+
+// Resolves on SIGTERM or SIGINT
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut term = signal(SignalKind::terminate()).expect("Failed to install SIGTERM handler");
+    let mut int = signal(SignalKind::interrupt()).expect("Failed to install SIGINT handler");
+
+    tokio::select! {
+        _ = term.recv() => {},
+        _ = int.recv() => {},
+    }
 }
 
 
+// Accounts named in the config are admins, along with any made by `executor admin`
+fn seed_admins() -> anyhow::Result<()> {
+    for username in &CONFIG.auth.admins {
+        DB.set_admin(username, true)?;
+    }
+    Ok(())
+}
+
+fn command(args: &[String]) -> anyhow::Result<()> {
+    match args {
+        [command, username] if command == "admin" => make_admin(username),
+        [command] if command == "similarity" => {
+            println!("Saved {} similarity reports", check_similarity()?);
+            Ok(())
+        },
+        _ => Err(anyhow::anyhow!("Usage: executor [admin <username> | similarity]")),
+    }
+}
+
+// Makes an account an admin, first making the account with a password from stdin if there isn't one
+fn make_admin(username: &str) -> anyhow::Result<()> {
+    if DB.set_admin(username, true)? {
+        println!("{} is now an admin", username);
+        return Ok(())
+    }
+    if let Some(problem) = auth::check_username(username) {
+        return Err(anyhow::anyhow!(problem))
+    }
+
+    eprintln!("Password for {}:", username);
+    let mut password = String::new();
+    std::io::stdin().read_line(&mut password)?;
+    let password = password.trim_end_matches(&['\r', '\n'][..]);
+    if let Some(problem) = auth::check_password(password) {
+        return Err(anyhow::anyhow!(problem))
+    }
+    let hash = auth::hash_password(password)?;
+    DB.create_user(username, &hash)?.ok_or_else(|| anyhow::anyhow!("{} was just taken", username))?;
+    DB.set_admin(username, true)?;
+    println!("Made {} an admin", username);
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() {
-    pretty_env_logger::init();
-
     // We want to crash early if there's something wrong with the configs
     verify_config();
-    &STATIC_CONTENT[..];
+    logging::init();
+    lazy_static::initialize(&TEMPLATES);
+    lazy_static::initialize(&auth::DUMMY_HASH);
+    lazy_static::initialize(&DB);
+    seed_admins().expect("Failed to set admins");
+
+    // Anything on the command line is a one-off command instead of running the server
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(e) = command(&args) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return
+    }
+
+    lazy_static::initialize(&QUESTIONS);
+    metrics::init();
+    match read_to_string("./config.toml") {
+        Ok(config) => record(&None, None, Action::config_loaded(&config)),
+        Err(e) => warn!("Failed to read the config for the audit log: {}", e),
+    }
 
     // TODO: Config this
     // But theres also no way we get 100 simultaneous connections every 2 seconds
 
-    let run = run_filter();
-    // TODO: Maybe this can be static instead of doing this arc bs
-    let page = warp::get()
-        .and(warp::path::end())
-        .map(|| warp::reply::html(STATIC_CONTENT.as_bytes()));
-
-    let get_boilerplate = warp::get()
-        .and(warp::path("boilerplate"))
-        .and(warp::path::param::<String>())
-        .and(warp::path::param::<String>())
-        .and_then(get_boilerplate)
-        .with(
-            // Probably want to make this not *
-            warp::reply::with::default_header("Access-Control-Allow-Origin", "*")
-        );
+    let router = routes();
+
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let (_, server) = warp::serve(router)
+        .bind_with_graceful_shutdown(([127, 0, 0, 1], 8080), async { stopped.await.ok(); });
+    let server = tokio::spawn(server);
+    tokio::spawn(refresh_readiness());
+    tokio::spawn(refresh_similarity());
+
+    shutdown_signal().await;
+    info!("Shutting down, waiting up to {:?} for running submissions", *SHUTDOWN_TIMEOUT);
+
+    // Stop taking new submissions on open connections, and stop accepting new connections
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+    stop.send(()).ok();
+
+    let drained = async {
+        server.await.ok();
+        // Queued submissions keep running after their connection is gone
+        while QUEUE.active() > 0 {
+            tokio::time::delay_for(Duration::from_millis(100)).await;
+        }
+    };
+
+    if tokio::time::timeout(*SHUTDOWN_TIMEOUT, drained).await.is_err() {
+        warn!("Submissions still running after {:?}, killing them", *SHUTDOWN_TIMEOUT);
+    }
 
-    let router = run.or(get_boilerplate).or(page);
-    warp::serve(router).run(([127, 0, 0, 1], 8080)).await;
+    kill_all_containers().await;
+    tokio::task::spawn_blocking(|| AUDIT.flush()).await.ok();
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use std::net::{IpAddr, Ipv4Addr};
+    use serde_json::Value;
+
+    // Make a user with a token, skipping the slow password hashing
+    // Returns the authorization header to send
+    // Tests share the database, so users like admin may already be there
+    // Admins from the config are made admins, as they would be on start
+    fn log_in_as(username: &str) -> String {
+        if DB.create_user(username, "").unwrap().is_some() {
+            seed_admins().unwrap();
+        }
+        let user = DB.user_by_name(username).unwrap().unwrap().0;
+        let (token, hash) = auth::new_token();
+        DB.create_token(user.id, &hash, TokenKind::Api, "test", None).unwrap();
+        format!("Bearer {}", token)
+    }
+
+    fn json<T: serde::de::DeserializeOwned>(res: &warp::http::Response<bytes::Bytes>) -> T {
+        serde_json::from_slice(res.body()).unwrap()
+    }
 
     #[tokio::test]
     async fn test_run_spam() {
+        let auth = log_in_as("spam");
         let (handle, question) = CONFIG.questions.iter().next().unwrap();
 
         let val1 = warp::test::request()
             .method("POST")
-            .remote_addr(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 14)), 8080))
-            .path(&format!("/run/{}", handle))
+            .header("authorization", &auth)
+            .path(&format!("/api/v1/run/{}/python", handle))
             .body(&format!("def {}:\n    return False", question.function_name));
 
         let val2 = warp::test::request()
             .method("POST")
-            .remote_addr(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 14)), 8080))
-            .path(&format!("/run/{}", handle))
+            .header("authorization", &auth)
+            .path(&format!("/api/v1/run/{}/python", handle))
             .body(&format!("def {}:\n    return False", question.function_name));
 
         let res1 = tokio::spawn(async move {
-            let filter = run_filter();
+            let filter = routes();
             val1.reply(&filter).await
             }
         );
         let res2 = tokio::spawn(async move {
-            let filter = run_filter();
+            let filter = routes();
             val2.reply(&filter).await
             }
         );
 
+        let res2 = res2.await.unwrap();
+        assert_eq!(res2.status(), StatusCode::TOO_MANY_REQUESTS);
         assert_eq!(
-            from_utf8(res2.await.unwrap().body()).unwrap(),
-            json!({"sucess": false, "msg": "Code ran too soon. Please wait a little."}).to_string()
+            from_utf8(res2.body()).unwrap(),
+            json!({"error": {"code": "rate_limited", "message": "Code ran too soon. Please wait a little."}}).to_string()
         );
 
         res1.await.unwrap();
     }
 
+    #[tokio::test]
+    async fn test_submissions() {
+        let auth = log_in_as("submissions");
+        let (handle, question) = CONFIG.questions.iter().next().unwrap();
+        let filter = routes();
+
+        let res = warp::test::request()
+            .method("POST")
+            .header("authorization", &auth)
+            .path(&format!("/api/v1/submissions/{}/python", handle))
+            // Code nothing else runs, so it isn't answered from the cache
+            .body(format!("def {}:\n    return 'submissions'", question.function_name))
+            .reply(&filter).await;
+
+        assert_eq!(res.status(), StatusCode::ACCEPTED);
+        let id = json::<Value>(&res)["id"].as_u64().unwrap();
+
+        let res = warp::test::request()
+            .header("authorization", &auth)
+            .path(&format!("/api/v1/submissions/{}", id))
+            .reply(&filter).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let status = json::<Value>(&res);
+        assert!(status["status"] == "queued" || status["status"] == "running");
+
+        // Nobody else can see it, except admins
+        let res = warp::test::request()
+            .header("authorization", &log_in_as("submissions_other"))
+            .path(&format!("/api/v1/submissions/{}", id))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let res = warp::test::request()
+            .header("authorization", &log_in_as("admin"))
+            .path(&format!("/api/v1/submissions/{}", id))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = warp::test::request()
+            .header("authorization", &auth)
+            .path("/api/v1/submissions/123456789")
+            .reply(&filter).await;
+
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            from_utf8(res.body()).unwrap(),
+            json!({"error": {"code": "not_found", "message": "No such submission."}}).to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_errors() {
+        let filter = routes();
+
+        let res = warp::test::request()
+            .method("POST")
+            .header("authorization", &log_in_as("errors1"))
+            .path("/api/v1/submissions/not_a_question/python")
+            .body("")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(json::<Value>(&res)["error"]["code"], "unknown_question");
+
+        let res = warp::test::request()
+            .method("POST")
+            .header("authorization", &log_in_as("errors2"))
+            .path("/api/v1/submissions/identity/python")
+            .body(&b"\xff"[..])
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(json::<Value>(&res)["error"]["code"], "invalid_encoding");
+
+        let res = warp::test::request()
+            .path("/api/v1/nothing/here")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(json::<Value>(&res)["error"]["code"], "not_found");
+    }
+
+    #[tokio::test]
+    async fn test_submission_events() {
+        let auth = log_in_as("events");
+        let (handle, question) = CONFIG.questions.iter().next().unwrap();
+        let filter = routes();
+
+        let res = warp::test::request()
+            .method("POST")
+            .header("authorization", &auth)
+            .path(&format!("/api/v1/submissions/{}/python", handle))
+            // Code nothing else runs, so it isn't answered from the cache
+            .body(format!("def {}:\n    return 'events'", question.function_name))
+            .reply(&filter).await;
+        let id = json::<Value>(&res)["id"].as_u64().unwrap();
+
+        let res = warp::test::request()
+            .header("authorization", &log_in_as("events_other"))
+            .path(&format!("/api/v1/submissions/{}/events", id))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        // Only returns once the submission has finished
+        let res = warp::test::request()
+            .header("authorization", &auth)
+            .path(&format!("/api/v1/submissions/{}/events", id))
+            .reply(&filter).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = from_utf8(res.body()).unwrap();
+        assert!(body.starts_with("event:status\ndata:{\"status\":\"queued\""));
+        assert!(body.contains("data:{\"status\":\"running\"}"));
+        assert!(body.contains("data:{\"status\":\"finished\""));
+    }
+
+    #[tokio::test]
+    async fn test_questions() {
+        let filter = routes();
+
+        let res = warp::test::request()
+            .path("/api/v1/questions")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let questions = json::<Value>(&res);
+        assert_eq!(questions.as_array().unwrap().len(), CONFIG.questions.len());
+        assert_eq!(questions[1]["handle"], "identity");
+        assert_eq!(questions[1]["title"], "Identity");
+        assert_eq!(questions[1]["languages"], json!(["python", "java"]));
+
+        let res = warp::test::request()
+            .path("/api/v1/questions/identity")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let question = json::<Value>(&res);
+        assert_eq!(question["prompt"], CONFIG.questions["identity"].prompt);
+        assert!(question["prompt_html"].as_str().unwrap().contains("<code>identity</code>"));
+        assert_eq!(question["python"]["boilerplate"], CONFIG.questions["identity"].python.boilerplate);
+        assert_eq!(question["limits"]["time_ms"], CONFIG.timeout);
+
+        let res = warp::test::request()
+            .path("/api/v1/questions/not_a_question")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_cors() {
+        let filter = routes();
+
+        let res = warp::test::request()
+            .method("OPTIONS")
+            .header("Origin", "http://localhost:8080")
+            .header("Access-Control-Request-Method", "POST")
+            .header("Access-Control-Request-Headers", "content-type")
+            .path("/api/v1/submissions/identity/python")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["access-control-allow-origin"], "http://localhost:8080");
+
+        let res = warp::test::request()
+            .method("OPTIONS")
+            .header("Origin", "https://evil.example.com")
+            .header("Access-Control-Request-Method", "POST")
+            .path("/api/v1/submissions/identity/python")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert_eq!(json::<Value>(&res)["error"]["code"], "forbidden");
+
+        let res = warp::test::request()
+            .method("OPTIONS")
+            .header("Origin", "http://localhost:8080")
+            .header("Access-Control-Request-Method", "PUT")
+            .path("/api/v1/questions")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let res = warp::test::request()
+            .header("Origin", "http://127.0.0.1:8080")
+            .path("/api/v1/questions")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["access-control-allow-origin"], "http://127.0.0.1:8080");
+
+        let res = warp::test::request()
+            .header("Origin", "https://evil.example.com")
+            .path("/api/v1/questions")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        // Not a browser, so not cors
+        let res = warp::test::request()
+            .path("/api/v1/questions")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get("access-control-allow-origin").is_none());
+    }
+
     #[tokio::test]
     async fn test_run_max_req() {
 
@@ -227,12 +1627,12 @@ mod test {
             vals.push(
                 tokio::spawn(
                     async move {
-                        let filter = run_filter();
+                        let filter = routes();
                         let (handle, question) = CONFIG.questions.iter().next().unwrap();
                         warp::test::request()
                         .method("POST")
-                        .remote_addr(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 0, i as u8)), 8080))
-                        .path(&format!("/run/{}", handle))
+                        .header("authorization", &log_in_as(&format!("max_req{}", i)))
+                        .path(&format!("/api/v1/run/{}/python", handle))
                         .body(&format!("def {}:\n    import time\n    sleep(40)", question.function_name))
                         .reply(&filter).await
                     }
@@ -241,7 +1641,719 @@ mod test {
         }
 
         for v in vals {
-            v.await.unwrap();
+            let res = v.await.unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(json::<Value>(&res)["verdict"], "Accepted");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_route_table() {
+        let filter = routes();
+        let auth = log_in_as("routes");
+
+        // Handle first, then language, everywhere
+        let res = warp::test::request()
+            .method("POST")
+            .header("authorization", &auth)
+            .path("/api/v1/run/identity/java")
+            .body("int identity(int x) { return x; }")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(json::<Value>(&res)["verdict"], "Accepted");
+        assert_eq!(
+            json::<Value>(&res)["msg"],
+            format!("java {}: int identity(int x) {{ return x; }}", CONFIG.questions["identity"].java.func_call)
+        );
+
+        // Submitted by someone else, so the rate limit doesn't get in the way
+        let submitter = log_in_as("routes2");
+        let res = warp::test::request()
+            .method("POST")
+            .header("authorization", &submitter)
+            .path("/api/v1/submissions/identity/python")
+            .body("def identity(x):\n    return x")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::ACCEPTED);
+        let id = json::<Value>(&res)["id"].as_u64().unwrap();
+
+        let res = warp::test::request()
+            .header("authorization", &submitter)
+            .path(&format!("/api/v1/submissions/{}/events", id))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(from_utf8(res.body()).unwrap().contains("python identity: def identity(x):"));
+
+        let res = warp::test::request()
+            .header("authorization", &submitter)
+            .path(&format!("/api/v1/submissions/{}", id))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(json::<Value>(&res)["status"], "finished");
+        assert_eq!(json::<Value>(&res)["result"]["verdict"], "Accepted");
+
+        let res = warp::test::request()
+            .path("/api/v1/boilerplate/identity/java")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            json::<Value>(&res),
+            json!({"handle": "identity", "language": "java", "boilerplate": CONFIG.questions["identity"].java.boilerplate})
+        );
+
+        let res = warp::test::request()
+            .path("/")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(from_utf8(res.body()).unwrap().contains("href=\"/questions/identity\""));
+
+        let res = warp::test::request()
+            .path("/questions/identity")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        // Prompts are markdown
+        assert!(from_utf8(res.body()).unwrap().contains("<code>identity</code>"));
+
+        // The old language first order is gone
+        let res = warp::test::request()
+            .path("/api/v1/boilerplate/python/identity")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(json::<Value>(&res)["error"]["code"], "unknown_language");
+
+        let res = warp::test::request()
+            .method("POST")
+            .header("authorization", &log_in_as("routes3"))
+            .path("/api/v1/run/identity/cobol")
+            .body("")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(json::<Value>(&res)["error"]["code"], "unknown_language");
+
+        let res = warp::test::request()
+            .path("/api/v1/run/identity/python")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        let res = warp::test::request()
+            .path("/api/v1/run/identity")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_accounts() {
+        let filter = routes();
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/api/v1/users")
+            .json(&json!({"username": "carol", "password": "hunter22"}))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(json::<Value>(&res)["username"], "carol");
+        assert_eq!(json::<Value>(&res)["admin"], false);
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/api/v1/users")
+            .json(&json!({"username": "carol", "password": "hunter23"}))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/api/v1/users")
+            .json(&json!({"username": "dave", "password": "short"}))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        // Nobody can take a name that would be made an admin
+        let res = warp::test::request()
+            .method("POST")
+            .path("/api/v1/users")
+            .json(&json!({"username": CONFIG.auth.admins[0], "password": "hunter22"}))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/api/v1/login")
+            .json(&json!({"username": "carol", "password": "hunter23"}))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        // Same as a wrong password
+        let res = warp::test::request()
+            .method("POST")
+            .path("/api/v1/login")
+            .json(&json!({"username": "nobody", "password": "hunter22"}))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/api/v1/login")
+            .json(&json!({"username": "carol", "password": "hunter22"}))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let session = json::<Value>(&res)["token"].as_str().unwrap().to_string();
+        assert!(res.headers()["set-cookie"].to_str().unwrap().starts_with(&format!("session={};", session)));
+        assert!(res.headers()["set-cookie"].to_str().unwrap().ends_with("; Secure"));
+
+        // The cookie works just like a bearer token
+        let res = warp::test::request()
+            .header("cookie", format!("session={}", session))
+            .path("/api/v1/account")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(json::<Value>(&res)["username"], "carol");
+
+        let res = warp::test::request()
+            .method("POST")
+            .header("cookie", format!("session={}", session))
+            .path("/api/v1/tokens")
+            .json(&json!({"name": "ci"}))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let api_token = json::<Value>(&res)["token"].as_str().unwrap().to_string();
+        let api_token_id = json::<Value>(&res)["id"].as_i64().unwrap();
+
+        let res = warp::test::request()
+            .method("POST")
+            .header("cookie", format!("session={}", session))
+            .path("/api/v1/logout")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let res = warp::test::request()
+            .header("cookie", format!("session={}", session))
+            .path("/api/v1/account")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        // Api tokens outlive the session they were made in
+        let auth = format!("Bearer {}", api_token);
+        let res = warp::test::request()
+            .header("authorization", &auth)
+            .path("/api/v1/tokens")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(json::<Value>(&res)[0]["name"], "ci");
+        assert!(json::<Value>(&res)[0].get("token").is_none());
+
+        let res = warp::test::request()
+            .method("DELETE")
+            .header("authorization", &auth)
+            .path(&format!("/api/v1/tokens/{}", api_token_id))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let res = warp::test::request()
+            .header("authorization", &auth)
+            .path("/api/v1/account")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        // Nothing runs without logging in
+        let res = warp::test::request()
+            .method("POST")
+            .path("/api/v1/run/identity/python")
+            .body("def identity(x):\n    return x")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(json::<Value>(&res)["error"]["code"], "unauthorized");
+    }
+
+    #[tokio::test]
+    async fn test_history() {
+        let filter = routes();
+        let auth = log_in_as("history");
+
+        let res = warp::test::request()
+            .method("POST")
+            .header("authorization", &auth)
+            .path("/api/v1/run/identity/python")
+            .body("def identity(x):\n    return x")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = warp::test::request()
+            .header("authorization", &auth)
+            .path("/api/v1/questions/identity/history")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let history = json::<Value>(&res);
+        assert_eq!(history.as_array().unwrap().len(), 1);
+        assert_eq!(history[0]["language"], "python");
+        assert_eq!(history[0]["verdict"], "Accepted");
+        assert!(history[0].get("code").is_none());
+        let id = history[0]["id"].as_i64().unwrap();
+
+        let res = warp::test::request()
+            .header("authorization", &auth)
+            .path(&format!("/api/v1/history/{}", id))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let submission = json::<Value>(&res);
+        assert_eq!(submission["question"], "identity");
+        assert_eq!(submission["code"], "def identity(x):\n    return x");
+        assert_eq!(submission["tests"], json!([{"test": 1, "total": 1, "passed": true}]));
+
+        // Nobody else's business, except for admins
+        let res = warp::test::request()
+            .header("authorization", &log_in_as("snoop"))
+            .path(&format!("/api/v1/history/{}", id))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let res = warp::test::request()
+            .header("authorization", &log_in_as("admin"))
+            .path(&format!("/api/v1/history/{}", id))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = warp::test::request()
+            .header("authorization", &auth)
+            .path("/api/v1/questions/not_a_question/history")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let res = warp::test::request()
+            .path("/api/v1/questions/identity/history")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_contest() {
+        let filter = routes();
+        let auth = log_in_as("contestant");
+
+        let res = warp::test::request()
+            .header("authorization", &auth)
+            .path("/api/v1/contests/warmup")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(json::<Value>(&res)["status"]["state"], "ready");
+        assert_eq!(json::<Value>(&res)["score"]["solved"], 0);
+
+        let res = warp::test::request()
+            .method("POST")
+            .header("authorization", &auth)
+            .path("/api/v1/contests/warmup/submissions/identity/python")
+            .body("def identity(x):\n    return x")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert_eq!(json::<Value>(&res)["error"]["code"], "contest_not_open");
+
+        let res = warp::test::request()
+            .method("POST")
+            .header("authorization", &auth)
+            .path("/api/v1/contests/warmup/start")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let detail = json::<Value>(&res);
+        assert_eq!(detail["status"]["state"], "running");
+        assert_eq!(detail["status"]["deadline"].as_i64().unwrap(), detail["now"].as_i64().unwrap() + 5400);
+
+        let res = warp::test::request()
+            .method("POST")
+            .header("authorization", &auth)
+            .path("/api/v1/contests/warmup/run/identity/python")
+            .body("def identity(x):\n    return x")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(json::<Value>(&res)["verdict"], "Accepted");
+
+        let res = warp::test::request()
+            .header("authorization", &auth)
+            .path("/api/v1/contests")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let warmup = &json::<Value>(&res)[0];
+        assert_eq!(warmup["handle"], "warmup");
+        assert_eq!(warmup["score"]["solved"], 1);
+        assert_eq!(warmup["score"]["questions"][0]["question"], "identity");
+        assert_eq!(warmup["score"]["questions"][0]["attempts"], 0);
+
+        // Contest submissions are in the history too
+        let res = warp::test::request()
+            .header("authorization", &auth)
+            .path("/api/v1/questions/identity/history")
+            .reply(&filter).await;
+        assert_eq!(json::<Value>(&res).as_array().unwrap().len(), 1);
+
+        let res = warp::test::request()
+            .method("POST")
+            .header("authorization", &auth)
+            .path("/api/v1/contests/nothing/run/identity/python")
+            .body("")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(json::<Value>(&res)["error"]["code"], "unknown_contest");
+    }
+
+    #[tokio::test]
+    async fn test_scoreboard() {
+        let filter = routes();
+        let auth = log_in_as("scorer");
+        let admin = log_in_as("admin");
+
+        warp::test::request()
+            .method("POST")
+            .header("authorization", &auth)
+            .path("/api/v1/contests/warmup/start")
+            .reply(&filter).await;
+        let res = warp::test::request()
+            .method("POST")
+            .header("authorization", &auth)
+            .path("/api/v1/contests/warmup/run/identity/python")
+            .body("def identity(x):\n    return x")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let row = |board: &Value| board["rows"].as_array().unwrap().iter()
+            .find(|r| r["username"] == "scorer").cloned().unwrap();
+
+        let res = warp::test::request()
+            .path("/api/v1/scoreboard")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let board = json::<Value>(&res);
+        assert_eq!(board["scoring"], "icpc");
+        assert_eq!(board["questions"], json!(["both_odd", "identity"]));
+        assert_eq!(row(&board)["solved"], 1);
+
+        // The warmup scoreboard is only for admins
+        let res = warp::test::request()
+            .header("authorization", &auth)
+            .path("/api/v1/contests/warmup/scoreboard")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let res = warp::test::request()
+            .header("authorization", &admin)
+            .path("/api/v1/contests/warmup/scoreboard")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let board = json::<Value>(&res);
+        assert_eq!(board["scoring"], "ioi");
+        assert_eq!(board["questions"], json!(["identity", "both_odd"]));
+        assert_eq!(row(&board)["score"], 100);
+        assert_eq!(row(&board)["cells"][0]["points"], 100);
+
+        let res = warp::test::request()
+            .path("/scoreboard")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let page = String::from_utf8(res.body().to_vec()).unwrap();
+        assert!(page.contains("<td>scorer</td>"));
+
+        let res = warp::test::request()
+            .path("/scoreboard/warmup")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let res = warp::test::request()
+            .header("authorization", &admin)
+            .path("/scoreboard/warmup")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_result_cache() {
+        let filter = routes();
+        let run = |auth: String, path: &'static str, code: &'static str| {
+            let filter = filter.clone();
+            async move {
+                let start = Instant::now();
+                let res = warp::test::request()
+                    .method("POST")
+                    .header("authorization", &auth)
+                    .path(path)
+                    .body(code)
+                    .reply(&filter).await;
+                (res, start.elapsed())
+            }
+        };
+
+        let (first, _) = run(log_in_as("cache1"), "/api/v1/run/identity/python", "def identity(x):\n    return 'cached'").await;
+        assert_eq!(first.status(), StatusCode::OK);
+
+        // Line endings and a trailing newline don't matter
+        let auth = log_in_as("cache2");
+        let (res, elapsed) = run(auth.clone(), "/api/v1/run/identity/python", "def identity(x):\r\n    return 'cached'\n").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body(), first.body());
+        assert!(elapsed < Duration::from_secs(1));
+
+        // Still saved to the history
+        let res = warp::test::request()
+            .header("authorization", &auth)
+            .path("/api/v1/questions/identity/history")
+            .reply(&filter).await;
+        assert_eq!(json::<Value>(&res).as_array().unwrap().len(), 1);
+
+        let (res, _) = run(log_in_as("cache3"), "/api/v1/run/identity/python?fresh=true", "def identity(x):\n    return 'cached'").await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let (res, elapsed) = run(log_in_as("admin"), "/api/v1/run/identity/python?fresh=true", "def identity(x):\n    return 'cached'").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(elapsed >= Duration::from_secs(3));
+    }
+
+    #[tokio::test]
+    async fn test_similarity() {
+        let filter = routes();
+        let original = "boolean bothOdd(int a, int b) {\n    return a % 2 == 1 && b % 2 == 1;\n}";
+        let copied = "boolean bothOdd(int x, int y) { /* mine */ return x%2==1 && y%2==1; }";
+        for (name, code) in &[("copier1", original), ("copier2", copied)] {
+            let user = DB.create_user(name, "").unwrap().unwrap();
+            DB.save_submission(&NewSubmission {user_id: user.id, contest: None, question: "both_odd", language: Language::Java, code, reply: &Reply::internal_error(), tests: &[], created_at: 0}).unwrap();
         }
+        // Reports are only as fresh as the last batch check
+        assert!(check_similarity().unwrap() > 0);
+
+        let res = warp::test::request()
+            .header("authorization", &log_in_as("admin"))
+            .path("/api/v1/similarity/both_odd/java")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let report = json::<Value>(&res);
+        assert!(report["checked_at"].as_i64().unwrap() > 0);
+        let pair = report["pairs"].as_array().unwrap().iter()
+            .find(|p| p["first"]["username"] == "copier1" && p["second"]["username"] == "copier2")
+            .unwrap();
+        assert_eq!(pair["similarity"], 1.0);
+        assert!(!pair["second"]["regions"].as_array().unwrap().is_empty());
+
+        let res = warp::test::request()
+            .header("authorization", &log_in_as("admin"))
+            .path("/api/v1/similarity/both_odd/java?contest=warmup&threshold=0.5")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(json::<Value>(&res)["pairs"], json!([]));
+
+        let res = warp::test::request()
+            .header("authorization", &log_in_as("copier3"))
+            .path("/api/v1/similarity/both_odd/java")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let filter = routes();
+        let auth = log_in_as("metrics");
+
+        let run = || warp::test::request()
+            .method("POST")
+            .header("authorization", &auth)
+            .path("/api/v1/run/identity/python")
+            .body("def identity(x):\n    return 'metrics'")
+            .reply(&filter);
+        // The second one is too soon
+        let (first, second) = futures::join!(run(), run());
+        assert_eq!(first.status().as_u16() + second.status().as_u16(), 200 + 429);
+
+        let res = warp::test::request()
+            .path("/metrics")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let text = from_utf8(res.body()).unwrap();
+        assert!(text.contains("code_quiz_submissions_total{language=\"python\",question=\"identity\",verdict=\"Accepted\"}"));
+        assert!(text.contains("code_quiz_judge_seconds_count{language=\"python\",question=\"identity\"}"));
+        assert!(text.contains("code_quiz_rate_limited_total"));
+    }
+
+    #[tokio::test]
+    async fn test_pages() {
+        let filter = routes();
+        let auth = log_in_as("pages");
+        let user = DB.user_by_name("pages").unwrap().unwrap().0;
+        DB.save_submission(&NewSubmission {user_id: user.id, contest: None, question: "identity", language: Language::Python, code: "", reply: &Reply::new(Verdict::Accepted, "", None), tests: &[], created_at: 0}).unwrap();
+        DB.save_submission(&NewSubmission {user_id: user.id, contest: None, question: "both_odd", language: Language::Python, code: "", reply: &Reply::new(Verdict::WrongAnswer, "", None), tests: &[], created_at: 0}).unwrap();
+
+        let res = warp::test::request()
+            .header("authorization", &auth)
+            .path("/")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let page = from_utf8(res.body()).unwrap();
+        assert!(page.contains("<td>Solved</td>"));
+        assert!(page.contains("<td>Attempted</td>"));
+        assert!(page.contains("<a href=\"/questions/both_odd\">Both Odd</a>"));
+
+        // Logged out there's nothing to show
+        let res = warp::test::request()
+            .path("/")
+            .reply(&filter).await;
+        assert!(!from_utf8(res.body()).unwrap().contains("Status"));
+
+        let res = warp::test::request()
+            .path("/questions/both_odd")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let page = from_utf8(res.body()).unwrap();
+        assert!(page.contains("<title>Both Odd</title>"));
+        assert!(page.contains("<pre>2, 3</pre>"));
+        assert!(page.contains("<option value=\"java\">java</option>"));
+        assert!(page.contains("<meta name=\"api\" content=\"/api/v1\">"));
+
+        // Scripts are served from here rather than a cdn
+        let res = warp::test::request()
+            .path("/static/jquery.min.js")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!page.contains("https://"));
+
+        let res = warp::test::request()
+            .path("/questions/nothing")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_admin_questions() {
+        let filter = routes();
+        let admin = log_in_as("admin");
+        let send = |method: &str, path: &str, json: Option<Value>| {
+            let req = warp::test::request().method(method).header("authorization", &admin).path(path);
+            match json {
+                Some(json) => req.json(&json),
+                None => req,
+            }
+        };
+        let mut question = serde_json::to_value(&CONFIG.questions["both_odd"]).unwrap();
+        question["handle"] = json!("admin_both_odd");
+
+        // Drafts don't need reference solutions, but aren't served
+        let res = send("POST", "/api/v1/admin/questions", Some(json!({"question": question}))).reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        assert_eq!(json::<Value>(&res)["enabled"], false);
+        let res = send("POST", "/api/v1/admin/questions", Some(json!({"question": question}))).reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        let res = warp::test::request()
+            .path("/api/v1/questions/admin_both_odd")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let res = send("POST", "/api/v1/admin/questions/admin_both_odd/enable", None).reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert!(json::<Value>(&res)["error"]["message"].as_str().unwrap().contains("There's no python reference solution."));
+
+        question["python"]["solution"] = json!("def is_both_odd(x, y):\n    return x % 2 == 1 and y % 2 == 1");
+        let res = send("POST", "/api/v1/admin/questions/preview", Some(json!({"question": question}))).reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let preview = json::<Value>(&res);
+        assert_eq!(preview["question"]["title"], "Both Odd");
+        assert_eq!(preview["problems"], json!(["There's no java reference solution."]));
+        assert_eq!(preview["solutions"][0]["reply"]["verdict"], "Accepted");
+        assert_eq!(preview["solutions"][1]["reply"], Value::Null);
+
+        // Publishing runs every reference solution
+        question["java"]["solution"] = json!("boolean isBothOdd(int x, int y) { return x % 2 == 1 && y % 2 == 1; }");
+        question["java"]["time_limit"] = json!(5000);
+        let res = send("POST", "/api/v1/admin/questions/both_odd", Some(json!({"question": question, "enabled": true}))).reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let entry = json::<Value>(&res);
+        // The handle comes from the path
+        assert_eq!(entry["question"]["handle"], "both_odd");
+        assert_eq!(entry["question"]["java"]["time_limit"], 5000);
+        assert_eq!((entry["enabled"].clone(), entry["in_config"].clone(), entry["saved"].clone()), (json!(true), json!(true), json!(true)));
+        let res = warp::test::request()
+            .path("/api/v1/questions/both_odd")
+            .reply(&filter).await;
+        assert_eq!(json::<Value>(&res)["java"]["time_ms"], 5000);
+        assert_eq!(DB.saved_questions().unwrap().iter().filter(|(q, _)| q.handle == "both_odd").count(), 1);
+
+        let res = send("DELETE", "/api/v1/admin/questions/both_odd", None).reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        let res = send("DELETE", "/api/v1/admin/questions/admin_both_odd", None).reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        let res = send("GET", "/api/v1/admin/questions/admin_both_odd", None).reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let res = send("GET", "/api/v1/admin/questions", None).reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(json::<Value>(&res).as_array().unwrap().len(), CONFIG.questions.len());
+
+        let res = warp::test::request()
+            .header("authorization", &log_in_as("not_admin"))
+            .path("/api/v1/admin/questions")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let res = warp::test::request()
+            .path("/admin")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_audit() {
+        let filter = routes();
+
+        let res = warp::test::request()
+            .method("POST")
+            .header("authorization", &log_in_as("audited"))
+            .path("/api/v1/run/identity/python")
+            .body("def identity(x):\n    return 'audit'")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/api/v1/login")
+            .json(&json!({"username": "audited", "password": "wrong password"}))
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = warp::test::request()
+            .header("authorization", &log_in_as("admin"))
+            .path("/api/v1/audit?user=audited")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let events = json::<Value>(&res);
+        let actions: Vec<&str> = events.as_array().unwrap().iter().map(|e| e["action"].as_str().unwrap()).collect();
+        assert_eq!(actions, vec!["login_failed", "submitted"]);
+        assert_eq!(events[1]["question"], "identity");
+        // The id it was saved under, so it can be found in the history
+        let user = DB.user_by_name("audited").unwrap().unwrap().0;
+        assert_eq!(events[1]["submission"], DB.submissions(user.id, "identity").unwrap()[0].id);
+
+        // Reading the log is itself audited
+        let res = warp::test::request()
+            .header("authorization", &log_in_as("admin"))
+            .path("/api/v1/audit?action=admin&limit=1")
+            .reply(&filter).await;
+        let events = json::<Value>(&res);
+        assert_eq!(events[0]["did"], "read the audit log");
+
+        let res = warp::test::request()
+            .header("authorization", &log_in_as("audited"))
+            .path("/api/v1/audit")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_health() {
+        let filter = routes();
+
+        let res = warp::test::request()
+            .path("/healthz")
+            .reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body(), "ok");
+
+        // Depends on whether docker is running here
+        let res = warp::test::request()
+            .path("/readyz")
+            .reply(&filter).await;
+        let readiness = json::<Value>(&res);
+        let ready = readiness["ready"].as_bool().unwrap();
+        assert_eq!(res.status(), if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE });
+        assert_eq!(readiness["checks"][0]["name"], "docker");
     }
 }
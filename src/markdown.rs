@@ -0,0 +1,82 @@
+use ammonia::Builder;
+use lazy_static::lazy_static;
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+
+// Images bundled with a question are served from `<ASSETS_PATH>/<handle>/<file>`
+pub const ASSETS_PATH: &str = "/question-assets";
+
+lazy_static! {
+    // Markdown can have any html in it, so everything it renders to goes through this
+    static ref SANITIZER: Builder<'static> = {
+        let mut builder = Builder::default();
+        // Math is left for the page to typeset
+        builder.add_allowed_classes("span", &["math", "math-inline", "math-display"])
+            .add_tag_attributes("code", &["class"])
+            .attribute_filter(|element, attribute, value| match (element, attribute) {
+                // Fenced code blocks say what they're written in, for highlighting
+                ("code", "class") if is_language_class(value) => Some(value.into()),
+                ("code", "class") => None,
+                _ => Some(value.into()),
+            });
+        builder
+    };
+}
+
+fn is_language_class(class: &str) -> bool {
+    class.strip_prefix("language-").is_some_and(|l| !l.is_empty() && l.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+// Images like `![graph](graph.png)` are bundled with the question
+fn is_relative(url: &str) -> bool {
+    !url.is_empty() && !url.starts_with('/') && !url.starts_with('#') && !url.contains(':')
+}
+
+// A question's prompt as sanitized html
+pub fn render(markdown: &str, handle: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_MATH | Options::ENABLE_STRIKETHROUGH;
+    let events = Parser::new_ext(markdown, options).map(|event| match event {
+        Event::Start(Tag::Image {link_type, dest_url, title, id}) if is_relative(&dest_url) => {
+            let dest_url = CowStr::from(format!("{}/{}/{}", ASSETS_PATH, handle, dest_url));
+            Event::Start(Tag::Image {link_type, dest_url, title, id})
+        },
+        event => event,
+    });
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, events);
+    SANITIZER.clean(&unsafe_html).to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            render("Implement `identity`, returning $x$.", "identity"),
+            "<p>Implement <code>identity</code>, returning <span class=\"math math-inline\">x</span>.</p>\n"
+        );
+        assert_eq!(
+            render("```python\ndef f(): pass\n```", "q"),
+            "<pre><code class=\"language-python\">def f(): pass\n</code></pre>\n"
+        );
+        assert!(render("| a | b |\n|---|---|\n| 1 | 2 |", "q").contains("<td>1</td>"));
+        assert!(render("$$\\sum_i x_i$$", "q").contains("<span class=\"math math-display\">\\sum_i x_i</span>"));
+    }
+
+    #[test]
+    fn test_images() {
+        assert!(render("![graph](graph.png)", "paths").contains("src=\"/question-assets/paths/graph.png\""));
+        assert!(render("![graph](https://example.com/graph.png)", "paths").contains("src=\"https://example.com/graph.png\""));
+        assert!(render("![graph](/graph.png)", "paths").contains("src=\"/graph.png\""));
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let html = render("<script>alert(1)</script>\n\n<b onclick=\"alert(1)\">hi</b>", "q");
+        assert!(!html.contains("script") && !html.contains("onclick"));
+        assert!(html.contains("<b>hi</b>"));
+        assert_eq!(render("[x](javascript:alert(1))", "q"), "<p><a rel=\"noopener noreferrer\">x</a></p>\n");
+        assert_eq!(render("<code class=\"evil x\">x</code>", "q"), "<p><code>x</code></p>\n");
+    }
+}
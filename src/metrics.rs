@@ -0,0 +1,87 @@
+use lazy_static::lazy_static;
+use prometheus::{
+    Encoder, HistogramVec, IntCounter, IntCounterVec, IntGauge, TextEncoder,
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge,
+};
+
+// Submission level metrics are labeled by these
+const SUBMISSION_LABELS: &[&str] = &["language", "question"];
+
+lazy_static! {
+    pub static ref SUBMISSIONS: IntCounterVec = register_int_counter_vec!(
+        "code_quiz_submissions_total", "Judged submissions, by verdict",
+        &["language", "question", "verdict"]
+    ).unwrap();
+    pub static ref CACHE_HITS: IntCounterVec = register_int_counter_vec!(
+        "code_quiz_cache_hits_total", "Submissions answered with the verdict of identical code", SUBMISSION_LABELS
+    ).unwrap();
+    pub static ref RATE_LIMITED: IntCounter = register_int_counter!(
+        "code_quiz_rate_limited_total", "Submissions turned away for coming too soon after the last one"
+    ).unwrap();
+    pub static ref QUEUE_WAIT: HistogramVec = register_histogram_vec!(
+        "code_quiz_queue_wait_seconds", "Time submissions spend waiting for a container slot", SUBMISSION_LABELS
+    ).unwrap();
+    pub static ref JUDGE_TIME: HistogramVec = register_histogram_vec!(
+        "code_quiz_judge_seconds", "Time taken to judge a submission once it has a slot", SUBMISSION_LABELS
+    ).unwrap();
+    // How much of `max_proc` is in use
+    pub static ref RUNNING: IntGauge = register_int_gauge!(
+        "code_quiz_running_submissions", "Submissions holding a container slot"
+    ).unwrap();
+    pub static ref QUEUED: IntGauge = register_int_gauge!(
+        "code_quiz_queued_submissions", "Submissions waiting for a container slot"
+    ).unwrap();
+    pub static ref EXEC_TIME: HistogramVec = register_histogram_vec!(
+        "code_quiz_exec_seconds", "Time taken by commands run for a submission", &["command"]
+    ).unwrap();
+    pub static ref EXEC_TIMEOUTS: IntCounterVec = register_int_counter_vec!(
+        "code_quiz_exec_timeouts_total", "Commands killed for running too long", &["command"]
+    ).unwrap();
+    pub static ref CONTAINER_START: HistogramVec = register_histogram_vec!(
+        "code_quiz_container_start_seconds", "Time taken to start a container", &["image"]
+    ).unwrap();
+    pub static ref CONTAINER_START_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "code_quiz_container_start_failures_total", "Containers that failed to start", &["image"]
+    ).unwrap();
+    pub static ref CONTAINERS: IntGauge = register_int_gauge!(
+        "code_quiz_containers", "Containers currently alive"
+    ).unwrap();
+}
+
+// Registers everything, so metrics that haven't changed yet still show up as 0
+pub fn init() {
+    lazy_static::initialize(&SUBMISSIONS);
+    lazy_static::initialize(&CACHE_HITS);
+    lazy_static::initialize(&RATE_LIMITED);
+    lazy_static::initialize(&QUEUE_WAIT);
+    lazy_static::initialize(&JUDGE_TIME);
+    lazy_static::initialize(&RUNNING);
+    lazy_static::initialize(&QUEUED);
+    lazy_static::initialize(&EXEC_TIME);
+    lazy_static::initialize(&EXEC_TIMEOUTS);
+    lazy_static::initialize(&CONTAINER_START);
+    lazy_static::initialize(&CONTAINER_START_FAILURES);
+    lazy_static::initialize(&CONTAINERS);
+}
+
+// Everything registered, in the prometheus text format
+pub fn render() -> String {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer).expect("Failed to encode metrics");
+    String::from_utf8(buffer).expect("Metrics should be UTF-8")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        SUBMISSIONS.with_label_values(&["python", "identity", "Accepted"]).inc();
+        QUEUE_WAIT.with_label_values(&["python", "identity"]).observe(0.5);
+
+        let text = render();
+        assert!(text.contains("code_quiz_submissions_total{language=\"python\",question=\"identity\",verdict=\"Accepted\"}"));
+        assert!(text.contains("code_quiz_queue_wait_seconds_bucket{language=\"python\",question=\"identity\",le=\"1\"}"));
+    }
+}
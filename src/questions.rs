@@ -0,0 +1,200 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use crate::config::{Language, Question, LANGUAGES};
+
+// A question as admins see it
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct Entry {
+    pub question: Question,
+    pub enabled: bool,
+    // In config.toml, so it can't be deleted
+    pub in_config: bool,
+    // Saved through the admin api, replacing any in config.toml
+    pub saved: bool,
+}
+
+// Every question, from config.toml and the admin api
+// Saved questions replace ones in the config with the same handle
+pub struct Questions {
+    config: HashMap<String, Arc<Question>>,
+    saved: RwLock<HashMap<String, (Arc<Question>, bool)>>,
+}
+
+impl Questions {
+    pub fn new(config: &HashMap<String, Question>, saved: Vec<(Question, bool)>) -> Questions {
+        Questions {
+            config: config.iter().map(|(h, q)| (h.to_string(), Arc::new(q.clone()))).collect(),
+            saved: RwLock::new(saved.into_iter().map(|(q, enabled)| (q.handle.to_string(), (Arc::new(q), enabled))).collect()),
+        }
+    }
+
+    fn lookup(&self, saved: &HashMap<String, (Arc<Question>, bool)>, handle: &str) -> Option<(Arc<Question>, bool)> {
+        saved.get(handle).cloned().or_else(|| self.config.get(handle).map(|q| (q.clone(), true)))
+    }
+
+    // Disabled questions can't be seen or answered by anyone but admins
+    pub fn get(&self, handle: &str) -> Option<Arc<Question>> {
+        match self.lookup(&self.saved.read().unwrap(), handle) {
+            Some((question, true)) => Some(question),
+            _ => None,
+        }
+    }
+
+    // Enabled questions, by handle
+    pub fn enabled(&self) -> Vec<Arc<Question>> {
+        let saved = self.saved.read().unwrap();
+        let mut handles: Vec<&String> = self.config.keys().chain(saved.keys()).collect();
+        handles.sort();
+        handles.dedup();
+        handles.into_iter()
+            .filter_map(|h| match self.lookup(&saved, h) {
+                Some((question, true)) => Some(question),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn entry(&self, handle: &str) -> Option<Entry> {
+        let saved = self.saved.read().unwrap();
+        self.lookup(&saved, handle).map(|(question, enabled)| Entry {
+            question: (*question).clone(),
+            enabled,
+            in_config: self.config.contains_key(handle),
+            saved: saved.contains_key(handle),
+        })
+    }
+
+    // Every question, enabled or not, by handle
+    pub fn entries(&self) -> Vec<Entry> {
+        let mut handles: Vec<String> = self.config.keys().chain(self.saved.read().unwrap().keys()).cloned().collect();
+        handles.sort();
+        handles.dedup();
+        handles.iter().filter_map(|h| self.entry(h)).collect()
+    }
+
+    pub fn in_config(&self, handle: &str) -> bool {
+        self.config.contains_key(handle)
+    }
+
+    // Only changes what's in memory, the caller saves it
+    pub fn save(&self, question: Question, enabled: bool) {
+        self.saved.write().unwrap().insert(question.handle.to_string(), (Arc::new(question), enabled));
+    }
+
+    pub fn remove(&self, handle: &str) {
+        self.saved.write().unwrap().remove(handle);
+    }
+}
+
+// Milliseconds a question can give a submission at most
+const MAX_TIME_LIMIT: u32 = 60 * 1000;
+
+// What's wrong with a question, if anything, that would stop it from being saved
+pub fn problems(question: &Question) -> Vec<String> {
+    let mut problems = Vec::new();
+    if question.handle.is_empty() || !question.handle.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        problems.push("The handle has to be letters, numbers, - and _.".to_string());
+    }
+    if question.function_name.is_empty() {
+        problems.push("The function name is missing.".to_string());
+    }
+    if question.prompt.trim().is_empty() {
+        problems.push("The prompt is missing.".to_string());
+    }
+    for &language in &LANGUAGES {
+        let (test_case, boilerplate, time_limit) = match language {
+            Language::Python => (&question.python.test_case, &question.python.boilerplate, question.python.time_limit),
+            Language::Java => (&question.java.test_case, &question.java.boilerplate, question.java.time_limit),
+        };
+        if test_case.trim().is_empty() {
+            problems.push(format!("There are no {} tests.", language));
+        }
+        if boilerplate.trim().is_empty() {
+            problems.push(format!("The {} boilerplate is missing.", language));
+        }
+        if time_limit.is_some_and(|t| t == 0 || t > MAX_TIME_LIMIT) {
+            problems.push(format!("The {} time limit has to be between 1 and {}ms.", language, MAX_TIME_LIMIT));
+        }
+    }
+    if question.java.func_call.trim().is_empty() {
+        problems.push("The java function call is missing.".to_string());
+    }
+    problems
+}
+
+// Publishing also needs a reference solution to run in every language
+pub fn publish_problems(question: &Question) -> Vec<String> {
+    let mut problems = problems(question);
+    for &language in &LANGUAGES {
+        if question.solution(language).trim().is_empty() {
+            problems.push(format!("There's no {} reference solution.", language));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{Java, Python};
+
+    fn question(handle: &str) -> Question {
+        Question {
+            handle: handle.to_string(),
+            function_name: "reverse".to_string(),
+            prompt: "Reverse a list.".to_string(),
+            python: Python {
+                test_case: "[([[1, 2]], [2, 1])]".to_string(),
+                boilerplate: "def reverse(xs):\n    pass".to_string(),
+                ..Python::default()
+            },
+            java: Java {
+                test_case: "TestCase<int[], int[]> testCase[] = new TestCase[] {}".to_string(),
+                boilerplate: "public class Solution {}".to_string(),
+                func_call: "reverse(t.input)".to_string(),
+                ..Java::default()
+            },
+            ..Question::default()
+        }
+    }
+
+    #[test]
+    fn test_questions() {
+        let mut config = HashMap::new();
+        config.insert("a".to_string(), question("a"));
+        config.insert("b".to_string(), question("b"));
+        let questions = Questions::new(&config, vec![(question("c"), true)]);
+        let handles = |qs: Vec<Arc<Question>>| qs.iter().map(|q| q.handle.to_string()).collect::<Vec<_>>();
+        assert_eq!(handles(questions.enabled()), vec!["a", "b", "c"]);
+
+        // Saving over one from the config replaces it
+        questions.save(question("b"), false);
+        assert_eq!(handles(questions.enabled()), vec!["a", "c"]);
+        assert!(questions.get("b").is_none());
+        let entry = questions.entry("b").unwrap();
+        assert!(!entry.enabled && entry.in_config && entry.saved);
+
+        // And removing it brings the config's back
+        questions.remove("b");
+        assert!(questions.get("b").is_some());
+        questions.remove("c");
+        assert!(questions.get("c").is_none());
+        assert_eq!(questions.entries().len(), 2);
+    }
+
+    #[test]
+    fn test_problems() {
+        assert!(problems(&question("reverse")).is_empty());
+        assert_eq!(publish_problems(&question("reverse")).len(), 2);
+
+        let mut bad = question("not a handle");
+        bad.python.time_limit = Some(0);
+        bad.java.func_call = String::new();
+        assert_eq!(problems(&bad), vec![
+            "The handle has to be letters, numbers, - and _.",
+            "The python time limit has to be between 1 and 60000ms.",
+            "The java function call is missing.",
+        ]);
+    }
+}
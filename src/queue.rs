@@ -0,0 +1,214 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use crate::test::{Progress, Reply};
+
+pub type JobId = u64;
+
+// How many finished results are kept around for polling
+const FINISHED_CAPACITY: usize = 1000;
+// Events buffered per subscriber before it starts missing them
+const EVENT_CAPACITY: usize = 64;
+
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobStatus {
+    // `position` is 0 for the job that runs next
+    Queued { position: usize },
+    Running,
+    Finished { result: Reply },
+}
+
+// Something that happened to a job, sent to anyone watching it
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum JobEvent {
+    Status(JobStatus),
+    Progress(Progress),
+}
+
+// Keeps track of submissions waiting for, or running in, a container
+// This doesn't run anything itself, callers report each job as it starts and finishes
+pub struct Queue {
+    state: Mutex<State>,
+    capacity: usize,
+}
+
+struct State {
+    next_id: JobId,
+    queued: VecDeque<JobId>,
+    running: HashSet<JobId>,
+    // Who submitted each job that hasn't finished
+    owners: HashMap<JobId, i64>,
+    finished: lru::LruCache<JobId, (i64, Reply)>,
+    // Dropped once the job finishes, which ends every subscriber's stream
+    events: HashMap<JobId, broadcast::Sender<JobEvent>>,
+}
+
+impl State {
+    fn status(&mut self, id: JobId) -> Option<JobStatus> {
+        if let Some(position) = self.queued.iter().position(|&j| j == id) {
+            Some(JobStatus::Queued {position})
+        } else if self.running.contains(&id) {
+            Some(JobStatus::Running)
+        } else {
+            self.finished.get(&id).map(|(_, r)| JobStatus::Finished {result: r.clone()})
+        }
+    }
+
+    fn owner(&mut self, id: JobId) -> Option<i64> {
+        self.owners.get(&id).copied().or_else(|| self.finished.get(&id).map(|(owner, _)| *owner))
+    }
+
+    fn send(&self, id: JobId, event: JobEvent) {
+        if let Some(events) = self.events.get(&id) {
+            // Fails if nobody is subscribed, which is fine
+            events.send(event).ok();
+        }
+    }
+
+    // Queued jobs move up whenever one starts or leaves the queue
+    fn send_positions(&self) {
+        for (position, &id) in self.queued.iter().enumerate() {
+            self.send(id, JobEvent::Status(JobStatus::Queued {position}));
+        }
+    }
+}
+
+impl Queue {
+    pub fn new(capacity: usize) -> Queue {
+        Queue {
+            state: Mutex::new(State {
+                next_id: 0,
+                queued: VecDeque::new(),
+                running: HashSet::new(),
+                owners: HashMap::new(),
+                finished: lru::LruCache::new(FINISHED_CAPACITY),
+                events: HashMap::new(),
+            }),
+            capacity,
+        }
+    }
+
+    // Add a job submitted by the user `owner` to the back of the queue
+    // Returns None if there are already `capacity` jobs waiting
+    pub fn push(&self, owner: i64) -> Option<JobId> {
+        let mut state = self.state.lock().unwrap();
+        if state.queued.len() >= self.capacity {
+            return None
+        }
+
+        let id = state.next_id;
+        state.next_id += 1;
+        state.queued.push_back(id);
+        state.owners.insert(id, owner);
+        state.events.insert(id, broadcast::channel(EVENT_CAPACITY).0);
+        Some(id)
+    }
+
+    pub fn start(&self, id: JobId) {
+        let mut state = self.state.lock().unwrap();
+        state.queued.retain(|&j| j != id);
+        state.running.insert(id);
+        state.send(id, JobEvent::Status(JobStatus::Running));
+        state.send_positions();
+    }
+
+    pub fn progress(&self, id: JobId, progress: Progress) {
+        self.state.lock().unwrap().send(id, JobEvent::Progress(progress));
+    }
+
+    pub fn finish(&self, id: JobId, result: Reply) {
+        let mut state = self.state.lock().unwrap();
+        state.queued.retain(|&j| j != id);
+        state.running.remove(&id);
+        state.send(id, JobEvent::Status(JobStatus::Finished {result: result.clone()}));
+        state.events.remove(&id);
+        if let Some(owner) = state.owners.remove(&id) {
+            state.finished.put(id, (owner, result));
+        }
+        state.send_positions();
+    }
+
+    pub fn status(&self, id: JobId) -> Option<JobStatus> {
+        self.state.lock().unwrap().status(id)
+    }
+
+    // The user who submitted a job, for as long as its status is kept
+    pub fn owner(&self, id: JobId) -> Option<i64> {
+        self.state.lock().unwrap().owner(id)
+    }
+
+    // Get a job's current status, and a receiver for everything that happens to it after that
+    // The receiver is closed once the job has finished
+    pub fn subscribe(&self, id: JobId) -> Option<(JobStatus, broadcast::Receiver<JobEvent>)> {
+        let mut state = self.state.lock().unwrap();
+        let status = state.status(id)?;
+        let events = match state.events.get(&id) {
+            Some(events) => events.subscribe(),
+            // Already finished, so there's nothing more to send
+            None => broadcast::channel(1).1,
+        };
+        Some((status, events))
+    }
+
+    // Number of jobs that are queued or running
+    pub fn active(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.queued.len() + state.running.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_queue() {
+        let queue = Queue::new(2);
+
+        let first = queue.push(1).unwrap();
+        let second = queue.push(2).unwrap();
+        assert_eq!(queue.push(1), None);
+        assert_eq!(queue.owner(second), Some(2));
+
+        assert_eq!(queue.status(first), Some(JobStatus::Queued {position: 0}));
+        assert_eq!(queue.status(second), Some(JobStatus::Queued {position: 1}));
+
+        queue.start(first);
+        assert_eq!(queue.status(first), Some(JobStatus::Running));
+        assert_eq!(queue.status(second), Some(JobStatus::Queued {position: 0}));
+        assert_eq!(queue.active(), 2);
+
+        queue.finish(first, Reply::internal_error());
+        assert_eq!(queue.status(first), Some(JobStatus::Finished {result: Reply::internal_error()}));
+        assert_eq!(queue.active(), 1);
+        assert_eq!(queue.owner(first), Some(1));
+        assert!(queue.push(1).is_some());
+
+        assert_eq!(queue.status(100), None);
+        assert_eq!(queue.owner(100), None);
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let queue = Queue::new(2);
+
+        let first = queue.push(1).unwrap();
+        let second = queue.push(1).unwrap();
+        let (status, mut events) = queue.subscribe(second).unwrap();
+        assert_eq!(status, JobStatus::Queued {position: 1});
+
+        queue.start(first);
+        assert_eq!(events.try_recv().unwrap(), JobEvent::Status(JobStatus::Queued {position: 0}));
+
+        queue.start(second);
+        queue.progress(second, Progress::Compiled);
+        queue.finish(second, Reply::internal_error());
+        assert_eq!(events.try_recv().unwrap(), JobEvent::Status(JobStatus::Running));
+        assert_eq!(events.try_recv().unwrap(), JobEvent::Progress(Progress::Compiled));
+        assert_eq!(events.try_recv().unwrap(), JobEvent::Status(JobStatus::Finished {result: Reply::internal_error()}));
+        assert!(matches!(events.try_recv(), Err(broadcast::TryRecvError::Closed)));
+    }
+}
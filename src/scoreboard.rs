@@ -0,0 +1,229 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::test::Verdict;
+
+// Minutes added to a solved question's penalty for each wrong attempt before it, in ICPC scoring
+const WRONG_ATTEMPT_PENALTY: i64 = 20;
+// Points for fully solving a question, in IOI scoring
+const FULL_SCORE: u32 = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scoring {
+    // Ranked by questions solved, then by penalty time
+    #[default]
+    Icpc,
+    // Ranked by partial points for the tests passed, then by when the score was reached
+    Ioi,
+}
+
+// A submission that counts towards the scoreboard
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Entry {
+    pub user_id: i64,
+    pub username: String,
+    pub question: String,
+    pub verdict: Verdict,
+    // Distinct tests passed out of the question's total
+    pub passed: usize,
+    pub total: usize,
+    pub submitted_at: i64,
+}
+
+impl Entry {
+    fn points(&self) -> u32 {
+        if self.verdict == Verdict::Accepted {
+            FULL_SCORE
+        } else {
+            // Never more than full marks, whatever the harness claimed
+            (FULL_SCORE as usize * self.passed.min(self.total)).checked_div(self.total).unwrap_or(0) as u32
+        }
+    }
+}
+
+// Wrong attempts from one user's verdicts on a question, oldest first, and the index of the first accepted one
+// Anything after that doesn't count, and neither do compile errors or errors of the judge's own making
+pub fn attempts(verdicts: impl IntoIterator<Item=Verdict>) -> (usize, Option<usize>) {
+    let mut attempts = 0;
+    for (i, verdict) in verdicts.into_iter().enumerate() {
+        match verdict {
+            Verdict::Accepted => return (attempts, Some(i)),
+            Verdict::CompileError | Verdict::InternalError => {},
+            _ => attempts += 1,
+        }
+    }
+    (attempts, None)
+}
+
+// How someone did on one question
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct Cell {
+    pub question: String,
+    // Wrong attempts, counted by `attempts`
+    pub attempts: usize,
+    pub solved_at: Option<i64>,
+    pub points: u32,
+}
+
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct Row {
+    // Tied rows share a rank
+    pub rank: usize,
+    pub username: String,
+    pub solved: usize,
+    pub score: u32,
+    // Minutes
+    pub penalty: i64,
+    pub cells: Vec<Cell>,
+}
+
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct Scoreboard {
+    pub scoring: Scoring,
+    pub questions: Vec<String>,
+    pub rows: Vec<Row>,
+}
+
+// Rank everyone with an entry, plus `participants` who haven't submitted anything
+// `entries` should be oldest first
+// `start` is when each user's clock started, if there is one, and penalty time counts from there
+pub fn scoreboard(
+    scoring: Scoring,
+    questions: &[String],
+    entries: &[Entry],
+    participants: &HashMap<i64, String>,
+    start: impl Fn(i64) -> Option<i64>,
+) -> Scoreboard {
+    let mut users: HashMap<i64, &str> = participants.iter().map(|(id, name)| (*id, &name[..])).collect();
+    for e in entries {
+        users.insert(e.user_id, &e.username);
+    }
+
+    let mut rows: Vec<Row> = users.into_iter().map(|(user_id, username)| {
+        let start = start(user_id);
+        let mut penalty = 0;
+        let cells: Vec<Cell> = questions.iter().map(|q| {
+            let mine: Vec<&Entry> = entries.iter().filter(|e| e.user_id == user_id && &e.question == q).collect();
+            let (attempts, solved) = attempts(mine.iter().map(|e| e.verdict));
+            let mut cell = Cell {question: q.to_string(), attempts, solved_at: solved.map(|i| mine[i].submitted_at), points: 0};
+            // When the best points so far were reached
+            let mut best_at = None;
+            for e in &mine[..solved.map_or(mine.len(), |i| i + 1)] {
+                if e.points() > cell.points {
+                    cell.points = e.points();
+                    best_at = Some(e.submitted_at);
+                }
+            }
+
+            let elapsed = |at: i64| start.map_or(0, |s| (at - s).max(0) / 60);
+            penalty += match scoring {
+                Scoring::Icpc => cell.solved_at
+                    .map_or(0, |at| elapsed(at) + WRONG_ATTEMPT_PENALTY * cell.attempts as i64),
+                Scoring::Ioi => best_at.map_or(0, elapsed),
+            };
+            cell
+        }).collect();
+
+        Row {
+            rank: 0,
+            username: username.to_string(),
+            solved: cells.iter().filter(|c| c.solved_at.is_some()).count(),
+            score: cells.iter().map(|c| c.points).sum(),
+            penalty,
+            cells,
+        }
+    }).collect();
+
+    // Lower is better
+    let key = |r: &Row| match scoring {
+        Scoring::Icpc => (-(r.solved as i64), r.penalty),
+        Scoring::Ioi => (-(r.score as i64), r.penalty),
+    };
+    rows.sort_by(|a, b| key(a).cmp(&key(b)).then_with(|| a.username.cmp(&b.username)));
+    for i in 0..rows.len() {
+        rows[i].rank = if i > 0 && key(&rows[i]) == key(&rows[i - 1]) { rows[i - 1].rank } else { i + 1 };
+    }
+
+    Scoreboard {scoring, questions: questions.to_vec(), rows}
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(user_id: i64, question: &str, verdict: Verdict, passed: usize, submitted_at: i64) -> Entry {
+        Entry {
+            user_id,
+            username: format!("user{}", user_id),
+            question: question.to_string(),
+            verdict,
+            passed,
+            total: 4,
+            submitted_at,
+        }
+    }
+
+    fn entries() -> Vec<Entry> {
+        vec![
+            entry(1, "a", Verdict::WrongAnswer, 2, 60),
+            entry(1, "a", Verdict::Accepted, 4, 600),
+            entry(2, "a", Verdict::Accepted, 4, 300),
+            entry(2, "b", Verdict::WrongAnswer, 3, 900),
+            entry(3, "a", Verdict::Accepted, 4, 300),
+            entry(3, "b", Verdict::WrongAnswer, 1, 900),
+        ]
+    }
+
+    #[test]
+    fn test_icpc() {
+        let questions = vec!["a".to_string(), "b".to_string()];
+        let mut participants = HashMap::new();
+        participants.insert(4, "user4".to_string());
+
+        let board = scoreboard(Scoring::Icpc, &questions, &entries(), &participants, |_| Some(0));
+        let ranks: Vec<_> = board.rows.iter().map(|r| (r.rank, &r.username[..], r.solved, r.penalty)).collect();
+        assert_eq!(ranks, vec![
+            (1, "user2", 1, 5),
+            (1, "user3", 1, 5),
+            (3, "user1", 1, 10 + 20),
+            (4, "user4", 0, 0),
+        ]);
+        assert_eq!(board.rows[2].cells[0], Cell {question: "a".to_string(), attempts: 1, solved_at: Some(600), points: 100});
+    }
+
+    #[test]
+    fn test_attempts() {
+        use Verdict::*;
+        assert_eq!(attempts(vec![WrongAnswer, TimeLimit, Accepted, WrongAnswer]), (2, Some(2)));
+        assert_eq!(attempts(vec![RuntimeError, WrongAnswer]), (2, None));
+        // Not held against anyone
+        assert_eq!(attempts(vec![CompileError, InternalError, Accepted]), (0, Some(2)));
+
+        let board = scoreboard(Scoring::Icpc, &["a".to_string()], &[
+            entry(1, "a", InternalError, 0, 60),
+            entry(1, "a", CompileError, 0, 120),
+            entry(1, "a", Accepted, 4, 300),
+        ], &HashMap::new(), |_| Some(0));
+        assert_eq!(board.rows[0].penalty, 5);
+    }
+
+    #[test]
+    fn test_ioi() {
+        let questions = vec!["a".to_string(), "b".to_string()];
+
+        let board = scoreboard(Scoring::Ioi, &questions, &entries(), &HashMap::new(), |_| None);
+        let ranks: Vec<_> = board.rows.iter().map(|r| (r.rank, &r.username[..], r.score)).collect();
+        assert_eq!(ranks, vec![
+            (1, "user2", 175),
+            (2, "user3", 125),
+            (3, "user1", 100),
+        ]);
+    }
+
+    #[test]
+    fn test_points() {
+        assert_eq!(entry(1, "a", Verdict::WrongAnswer, 2, 0).points(), 50);
+        assert_eq!(entry(1, "a", Verdict::WrongAnswer, 9, 0).points(), 100);
+        assert_eq!(Entry {total: 0, ..entry(1, "a", Verdict::WrongAnswer, 2, 0)}.points(), 0);
+    }
+}
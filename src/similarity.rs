@@ -0,0 +1,262 @@
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use crate::config::Language;
+
+// Tokens in a row that have to match for it to count as copied
+const K: usize = 5;
+// Every run of this many k-grams keeps at least one fingerprint
+const WINDOW: usize = 4;
+// Pairs at least this similar are reported by default
+pub const DEFAULT_THRESHOLD: f64 = 0.8;
+// Pairs at least this similar are kept by the batch check, so lower thresholds show the same pairs
+pub const STORED_THRESHOLD: f64 = 0.5;
+
+const PYTHON_KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+    "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "nonlocal",
+    "not", "or", "pass", "raise", "return", "try", "while", "with", "yield",
+];
+
+const JAVA_KEYWORDS: &[&str] = &[
+    "abstract", "boolean", "break", "byte", "case", "catch", "char", "class", "continue", "default", "do", "double",
+    "else", "extends", "false", "final", "finally", "float", "for", "if", "implements", "import", "instanceof", "int",
+    "interface", "long", "new", "null", "private", "protected", "public", "return", "short", "static", "super",
+    "switch", "this", "throw", "throws", "true", "try", "void", "while",
+];
+
+// Someone's submission to compare against everyone else's
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Source {
+    pub submission: i64,
+    pub username: String,
+    pub code: String,
+}
+
+// A token with names, literals, whitespace and comments normalized away
+// `start` and `end` are byte offsets into the code, for highlighting
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Token<'a> {
+    kind: &'a str,
+    start: usize,
+    end: usize,
+}
+
+fn tokenize(language: Language, code: &str) -> Vec<Token<'_>> {
+    let (line_comment, keywords) = match language {
+        Language::Python => ("#", PYTHON_KEYWORDS),
+        Language::Java => ("//", JAVA_KEYWORDS),
+    };
+    let bytes = code.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let rest = &code[i..];
+        let c = rest.chars().next().unwrap();
+        let start = i;
+
+        if c.is_whitespace() {
+            i += c.len_utf8();
+            continue
+        } else if rest.starts_with(line_comment) {
+            i += rest.find('\n').unwrap_or(rest.len());
+            continue
+        } else if language == Language::Java && rest.starts_with("/*") {
+            i += rest[2..].find("*/").map_or(rest.len(), |end| end + 4);
+            continue
+        }
+
+        let kind = if c == '"' || c == '\'' {
+            let quote = if language == Language::Python && (rest.starts_with("\"\"\"") || rest.starts_with("'''")) {
+                &rest[..3]
+            } else {
+                &rest[..1]
+            };
+            i += quote.len();
+            // Skip to the closing quote, minding escapes
+            while i < bytes.len() && !bytes[i..].starts_with(quote.as_bytes()) {
+                i += if bytes[i] == b'\\' { 2 } else { 1 };
+            }
+            i = (i + quote.len()).min(bytes.len());
+            "S"
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            i += len;
+            let word = &rest[..len];
+            if keywords.contains(&word) { word } else { "V" }
+        } else if c.is_ascii_digit() {
+            i += rest.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.')).unwrap_or(rest.len());
+            "N"
+        } else {
+            i += c.len_utf8();
+            &rest[..c.len_utf8()]
+        };
+        // Escapes can skip past the end, or into the middle of a character
+        while !code.is_char_boundary(i.min(bytes.len())) {
+            i += 1;
+        }
+        i = i.min(bytes.len());
+        tokens.push(Token {kind, start, end: i});
+    }
+    tokens
+}
+
+// Winnowed k-gram hashes, with the index of the k-gram each came from
+fn fingerprints(tokens: &[Token]) -> Vec<(u64, usize)> {
+    let hashes: Vec<u64> = tokens.windows(K).map(|gram| {
+        let mut hasher = DefaultHasher::new();
+        for t in gram {
+            t.kind.hash(&mut hasher);
+        }
+        hasher.finish()
+    }).collect();
+
+    let mut kept: Vec<(u64, usize)> = Vec::new();
+    // Code with fewer than WINDOW k-grams still gets one window
+    let windows = if hashes.is_empty() { 0 } else { hashes.len().saturating_sub(WINDOW - 1).max(1) };
+    for start in 0..windows {
+        let end = (start + WINDOW).min(hashes.len());
+        // The rightmost minimum, so the same one is picked again as the window slides
+        let (i, hash) = (start..end).map(|i| (i, hashes[i])).min_by_key(|&(i, h)| (h, std::cmp::Reverse(i))).unwrap();
+        if kept.last().map(|&(_, last)| last) != Some(i) {
+            kept.push((hash, i));
+        }
+    }
+    kept
+}
+
+// Sorted byte ranges, with overlapping ones joined
+fn merge(mut ranges: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    ranges.sort();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+// One submission in a pair, with the parts that match the other highlighted as byte ranges
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Side {
+    pub submission: i64,
+    pub username: String,
+    pub regions: Vec<(usize, usize)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Pair {
+    // Share of the smaller submission's fingerprints found in the other, from 0 to 1
+    pub similarity: f64,
+    pub first: Side,
+    pub second: Side,
+}
+
+// Pairs from the last batch check, which only looks at submissions made before `checked_at`
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Report {
+    pub checked_at: i64,
+    pub pairs: Vec<Pair>,
+}
+
+struct Fingerprinted<'a> {
+    source: &'a Source,
+    tokens: Vec<Token<'a>>,
+    // Hash to the k-grams it came from
+    grams: HashMap<u64, Vec<usize>>,
+}
+
+impl<'a> Fingerprinted<'a> {
+    fn new(language: Language, source: &'a Source) -> Fingerprinted<'a> {
+        let tokens = tokenize(language, &source.code);
+        let mut grams: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (hash, i) in fingerprints(&tokens) {
+            grams.entry(hash).or_default().push(i);
+        }
+        Fingerprinted {source, tokens, grams}
+    }
+
+    fn regions(&self, shared: &HashSet<u64>) -> Vec<(usize, usize)> {
+        let ranges = shared.iter()
+            .flat_map(|h| self.grams[h].iter())
+            .map(|&i| (self.tokens[i].start, self.tokens[i + K - 1].end))
+            .collect();
+        merge(ranges)
+    }
+
+    fn side(&self, shared: &HashSet<u64>) -> Side {
+        Side {
+            submission: self.source.submission,
+            username: self.source.username.to_string(),
+            regions: self.regions(shared),
+        }
+    }
+}
+
+// Compare every pair of submissions, which should all be in `language` and to the same question
+// Returns the pairs at least `threshold` similar, most similar first
+pub fn report(language: Language, sources: &[Source], threshold: f64) -> Vec<Pair> {
+    let fingerprinted: Vec<Fingerprinted> = sources.iter().map(|s| Fingerprinted::new(language, s)).collect();
+    let mut pairs = Vec::new();
+
+    for (i, a) in fingerprinted.iter().enumerate() {
+        for b in &fingerprinted[i + 1..] {
+            let smaller = a.grams.len().min(b.grams.len());
+            if smaller == 0 {
+                continue
+            }
+            let shared: HashSet<u64> = a.grams.keys().filter(|h| b.grams.contains_key(h)).cloned().collect();
+            let similarity = shared.len() as f64 / smaller as f64;
+            if similarity >= threshold {
+                pairs.push(Pair {similarity, first: a.side(&shared), second: b.side(&shared)});
+            }
+        }
+    }
+    pairs.sort_by(|a, b| b.similarity.partial_cmp(&a.similarity).unwrap());
+    pairs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn source(submission: i64, code: &str) -> Source {
+        Source {submission, username: format!("user{}", submission), code: code.to_string()}
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let kinds = |language, code| tokenize(language, code).iter().map(|t| t.kind).collect::<Vec<_>>();
+        assert_eq!(kinds(Language::Python, "def f(x):  # hi\n    return x + 'a\\'b'"),
+            vec!["def", "V", "(", "V", ")", ":", "return", "V", "+", "S"]);
+        assert_eq!(kinds(Language::Java, "/* hi */ int f(int x) { return x * 10; } // bye"),
+            vec!["int", "V", "(", "int", "V", ")", "{", "return", "V", "*", "N", ";", "}"]);
+
+        let tokens = tokenize(Language::Python, "x = \"\"\"doc\"\"\"");
+        assert_eq!(tokens[2], Token {kind: "S", start: 4, end: 13});
+    }
+
+    #[test]
+    fn test_report() {
+        let original = "def both_odd(a, b):\n    if a % 2 == 1 and b % 2 == 1:\n        return True\n    return False\n";
+        // Renamed, recommented and reformatted
+        let copied = "def both_odd(x,y):\n    # totally mine\n    if x%2==1 and y%2==1:\n        return True\n\n    return False";
+        let different = "def both_odd(a, b):\n    return all(n % 2 for n in [a, b])\n";
+
+        let sources = vec![source(1, original), source(2, copied), source(3, different)];
+        let pairs = report(Language::Python, &sources, DEFAULT_THRESHOLD);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].similarity, 1.0);
+        assert_eq!((pairs[0].first.submission, pairs[0].second.submission), (1, 2));
+        assert_eq!(pairs[0].first.regions, vec![(0, original.trim_end().len())]);
+
+        // Everything's similar to something at 0
+        assert_eq!(report(Language::Python, &sources, 0.0).len(), 3);
+        // Too short to fingerprint
+        assert!(report(Language::Python, &[source(1, "x"), source(2, "x")], 0.0).is_empty());
+    }
+}
@@ -1,75 +1,107 @@
-use crate::exec::CodeExec;
 use crate::exec::python::exec_python;
 use crate::exec::java::exec_java_in_container;
 use crate::exec::docker::exec_in_dangling_container;
-use serde::Serialize;
+use crate::exec::Lines;
+use crate::config::Language;
+use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
+pub mod verdict;
+
+pub use verdict::{Reply, Verdict};
+
+// Printed by `java main.java` when the source fails to compile
+const JAVA_COMPILE_FAILED: &str = "error: compilation failed";
+
+// test.py and template.java report progress on stderr, on lines starting with this and then the run's nonce
+pub const PROGRESS_PREFIX: &str = "#progress ";
+
+// A fresh secret for each run, so progress lines printed by the submission itself can be told apart
+pub fn nonce() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+// Progress reported by the test harness while it runs
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Progress {
+    Compiled,
+    // `test` counts from 1
+    Test { test: usize, total: usize, passed: bool },
+    // Every test case ran, which an Accepted verdict needs
+    Done,
+}
+
+impl Progress {
+    // Parse a line of the harness' stderr, which is None if it isn't a progress line with `nonce`
+    pub fn parse(line: &[u8], nonce: &str) -> Option<Progress> {
+        let line = std::str::from_utf8(line).ok()?;
+        let event = line.strip_prefix(PROGRESS_PREFIX)?.strip_prefix(nonce)?.strip_prefix(' ')?;
+        serde_json::from_str(event).ok()
+    }
+}
+
 
 // TODO: Make this not pub
 #[derive(Serialize)]
 pub struct Test<'a> {
     pub code: &'a str,
     pub test_case: &'a str,
+    pub nonce: &'a str,
 }
 
 
-// TODO: maybe want to make an enum or something for errors vs failures
-pub fn gen_reply(success:bool, message:&str) -> String {
-    use serde_json::json;
-    json!({"success": success, "msg": message}).to_string()
-}
-
-pub async fn test_python(code: &str, func_name: &str, test_case: &str, time: Duration) -> String {
+pub async fn test_python(code: &str, func_name: &str, test_case: &str, time: Duration) -> Reply {
 
     use serde_json::to_string;
 
+    let nonce = nonce();
     // TODO: error handling
-    let input = to_string(&Test{code, test_case}).unwrap();
+    let input = to_string(&Test{code, test_case, nonce: &nonce}).unwrap();
 
     // TODO: Read test file from config
     match exec_python(vec!["./test.py", func_name], time, Some(&input)).await {
-        Ok(CodeExec::Executed(Some(0), stdout, _)) => gen_reply(true, &stdout),
-        Ok(CodeExec::Executed(Some(1), _, stderr)) => gen_reply(false, &stderr),
-        Ok(CodeExec::Executed(Some(2), stdout, _)) => gen_reply(false, &stdout),
-        Ok(CodeExec::Timeout) => gen_reply(false, "Test timed out."),
-        _ => gen_reply(false, "A server error occured. Try again later."),
+        Ok(exec) => Reply::from_exec(&exec, None, &nonce),
+        Err(_) => Reply::internal_error(),
     }
 }
 
 
-pub async fn test_python_in_container(code: &str, func_name: &str, test_case: &str, time: Duration) -> String {
+// Harness progress is sent to `stderr_lines` as it runs, and can be read with `Progress::parse` and the same `nonce`
+pub async fn test_python_in_container(code: &str, func_name: &str, test_case: &str, time: Duration, nonce: &str, stderr_lines: Option<&Lines>) -> Reply {
 
     use serde_json::to_string;
 
     // TODO: error handling
-    let input = to_string(&Test{code, test_case}).unwrap();
+    let input = to_string(&Test{code, test_case, nonce}).unwrap();
 
     // TODO: Read test file from config
     // TODO: Generate everything from test file
-    match exec_in_dangling_container("test", vec!["python", "./test.py", func_name], time, Some(&input), true).await {
-        Ok(CodeExec::Executed(Some(0), stdout, _)) => gen_reply(true, &stdout),
-        Ok(CodeExec::Executed(Some(1), _, stderr)) => gen_reply(false, &stderr),
-        Ok(CodeExec::Executed(Some(2), stdout, _)) => gen_reply(false, &stdout),
-        Ok(CodeExec::Timeout) => gen_reply(false, "Test timed out."),
-        _ => gen_reply(false, "A server error occured. Try again later."),
+    match exec_in_dangling_container(Language::Python.image(), vec!["python", "./test.py", func_name], time, false, Some(&input), true, stderr_lines).await {
+        Ok((exec, usage)) => Reply::from_exec(&exec, usage, nonce),
+        Err(_) => Reply::internal_error(),
     }
 }
 
 
-pub async fn test_java_in_container(code: &str, template: &str, func_call: &str, test_case: &str, time: Duration) -> String {
+pub async fn test_java_in_container(code: &str, template: &str, func_call: &str, test_case: &str, time: Duration, nonce: &str, stderr_lines: Option<&Lines>) -> Reply {
 
     let mut new_source = template.to_string();
     new_source.push_str(code);
     let new_source = new_source.replace("{{test_case}}", test_case);
     let new_source = new_source.replace("{{func_call}}", func_call);
 
-    match exec_java_in_container(&new_source, "test", time, None).await {
-        Ok(CodeExec::Executed(Some(0), stdout, _)) => gen_reply(true, &stdout),
-        Ok(CodeExec::Executed(Some(1), _, stderr)) => gen_reply(false, &stderr),
-        Ok(CodeExec::Executed(Some(2), stdout, _)) => gen_reply(false, &stdout),
-        Ok(CodeExec::Timeout) => gen_reply(false, "Test timed out."),
-        _ => gen_reply(false, "A server error occured. Try again later."),
+    // The harness reads its nonce from stdin before running any tests
+    match exec_java_in_container(&new_source, Language::Java.image(), time, Some(&format!("{}\n", nonce)), stderr_lines).await {
+        Ok((exec, usage)) => {
+            let mut reply = Reply::from_exec(&exec, usage, nonce);
+            // The java source launcher compiles and runs in one go, so compile errors look like a crash
+            if reply.verdict == Verdict::RuntimeError && reply.msg.contains(JAVA_COMPILE_FAILED) {
+                reply.verdict = Verdict::CompileError;
+            }
+            reply
+        },
+        Err(_) => Reply::internal_error(),
     }
 }
 
@@ -86,15 +118,31 @@ def solution():
 
         let success = test_python(function, "solution", "[([], True)]", Duration::new(10, 0)).await;
 
-        assert_eq!(success, gen_reply(true, "All test cases passed!"));
+        assert_eq!(success, Reply::new(Verdict::Accepted, "All test cases passed!", None));
 
         let fail = test_python(function, "solution", "[([], False)]", Duration::new(10, 0)).await;
 
-        assert_eq!(fail, gen_reply(false, "Test case failed on input `[]`: Expected \n`False`\nbut got \n`True`"));
+        assert_eq!(fail, Reply::new(Verdict::WrongAnswer, "Test case failed on input `[]`: Expected \n`False`\nbut got \n`True`", None));
 
         let fail = test_python("while True:\n    pass", "solution", "[([], False)]", Duration::new(10, 0)).await;
 
-        assert_eq!(fail, gen_reply(false, "Test timed out."));
+        assert_eq!(fail, Reply::new(Verdict::TimeLimit, "Test timed out.", None));
+
+        let fail = test_python("def solution():\n    while True:\n        print('x')", "solution", "[([], False)]", Duration::new(10, 0)).await;
+
+        assert_eq!(fail, Reply::new(Verdict::OutputLimit, "Output limit exceeded.", None));
+
+        let function = "
+def solution():
+    import sys
+    sys.stdout.buffer.write(b'\\xff')
+    sys.stdout.flush()
+    return True
+        ".trim();
+
+        let binary = test_python(function, "solution", "[([], False)]", Duration::new(10, 0)).await;
+
+        assert_eq!(binary, Reply::new(Verdict::WrongAnswer, "\u{FFFD}Test case failed on input `[]`: Expected \n`False`\nbut got \n`True`", None));
 
         let function = "
 i = 0
@@ -106,6 +154,72 @@ def solution():
 
         let global_test = test_python(function, "solution", "[([], 1), ([], 1)]", Duration::new(10, 0)).await;
 
-        assert_eq!(global_test, gen_reply(true, "All test cases passed!"));
+        assert_eq!(global_test, Reply::new(Verdict::Accepted, "All test cases passed!", None));
+
+        let error = test_python("def solution():\n    return 1 / 0", "solution", "[([], 0)]", Duration::new(10, 0)).await;
+
+        assert_eq!(error.verdict, Verdict::RuntimeError);
+        assert!(error.msg.contains("ZeroDivisionError"));
+        assert!(!error.msg.contains(PROGRESS_PREFIX));
+
+        let exit = test_python("import os\nos._exit(0)", "solution", "[([], 0)]", Duration::new(10, 0)).await;
+
+        assert_eq!(exit, Reply::new(Verdict::RuntimeError, "Exited before all test cases ran.", None));
+    }
+
+    #[test]
+    fn test_parse_progress() {
+        assert_eq!(Progress::parse(b"#progress abc {\"event\": \"compiled\"}", "abc"), Some(Progress::Compiled));
+        assert_eq!(
+            Progress::parse(b"#progress abc {\"event\": \"test\", \"test\": 1, \"total\": 4, \"passed\": true}", "abc"),
+            Some(Progress::Test {test: 1, total: 4, passed: true})
+        );
+        assert_eq!(Progress::parse(b"Traceback (most recent call last):", "abc"), None);
+        // Without the run's nonce
+        assert_eq!(Progress::parse(b"#progress {\"event\": \"compiled\"}", "abc"), None);
+        assert_eq!(Progress::parse(b"#progress abd {\"event\": \"compiled\"}", "abc"), None);
+    }
+
+    #[tokio::test]
+    async fn test_forged_progress() {
+        // Claims to pass every test, then fails the only one
+        let code = "
+def solution():
+    import sys
+    print('#progress {\"event\": \"test\", \"test\": 1, \"total\": 1, \"passed\": true}', file=sys.stderr)
+    return False
+        ".trim();
+        let nonce = nonce();
+        let input = serde_json::to_string(&Test{code, test_case: "[([], True)]", nonce: &nonce}).unwrap();
+        let exec = exec_python(vec!["./test.py", "solution"], Duration::new(10, 0), Some(&input)).await.unwrap();
+
+        let stderr = match exec {
+            crate::exec::CodeExec::Executed(Some(2), _, stderr) => stderr,
+            e => panic!("Unexpected result {:?}", e),
+        };
+        let progress: Vec<Progress> = stderr.split(|&b| b == b'\n').filter_map(|l| Progress::parse(l, &nonce)).collect();
+        assert_eq!(progress, vec![Progress::Compiled, Progress::Test {test: 1, total: 1, passed: false}]);
+
+        // Looks for the nonce in the harness' frames, which run in another process
+        let code = "
+def solution():
+    import sys
+    frame = sys._getframe()
+    while frame:
+        nonce = frame.f_locals.get('nonce') or frame.f_globals.get('nonce')
+        if nonce:
+            print('#progress ' + nonce + ' {\"event\": \"test\", \"test\": 1, \"total\": 1, \"passed\": true}', file=sys.stderr)
+        frame = frame.f_back
+    return False
+        ".trim();
+        let input = serde_json::to_string(&Test{code, test_case: "[([], True)]", nonce: &nonce}).unwrap();
+        let exec = exec_python(vec!["./test.py", "solution"], Duration::new(10, 0), Some(&input)).await.unwrap();
+
+        let stderr = match exec {
+            crate::exec::CodeExec::Executed(Some(2), _, stderr) => stderr,
+            e => panic!("Unexpected result {:?}", e),
+        };
+        let progress: Vec<Progress> = stderr.split(|&b| b == b'\n').filter_map(|l| Progress::parse(l, &nonce)).collect();
+        assert_eq!(progress, vec![Progress::Compiled, Progress::Test {test: 1, total: 1, passed: false}]);
     }
 }
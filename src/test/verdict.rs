@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+use crate::exec::{CodeExec, Usage};
+use super::{Progress, PROGRESS_PREFIX};
+
+// Exit code test.py and template.java use when a test case fails
+const WRONG_ANSWER: i32 = 2;
+
+// How the docker cli starts its own errors, as opposed to anything the submission wrote
+const DOCKER_ERRORS: [&str; 4] = ["docker: ", "Error response from daemon", "OCI runtime", "Error: No such container"];
+
+// Final outcome of testing a submission
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Verdict {
+    Accepted,
+    WrongAnswer,
+    RuntimeError,
+    TimeLimit,
+    MemoryLimit,
+    OutputLimit,
+    CompileError,
+    InternalError,
+}
+
+// What gets sent back for a submission
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct Reply {
+    pub success: bool,
+    pub verdict: Verdict,
+    pub msg: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+impl Reply {
+    pub fn new(verdict: Verdict, msg: &str, usage: Option<Usage>) -> Reply {
+        Reply {
+            success: verdict == Verdict::Accepted,
+            verdict,
+            msg: msg.to_string(),
+            usage,
+        }
+    }
+
+    pub fn internal_error() -> Reply {
+        Reply::new(Verdict::InternalError, "A server error occured. Try again later.", None)
+    }
+
+    // Judge an execution of the test harness
+    // `usage` is only available for code run in a container, and is needed to spot OOM kills
+    // `nonce` is the one the harness was given, whose final progress line is needed for an Accepted verdict
+    pub fn from_exec(exec: &CodeExec, usage: Option<Usage>, nonce: &str) -> Reply {
+        let oom_killed = usage.as_ref().is_some_and(|u| u.oom_killed);
+
+        match exec {
+            CodeExec::Timeout => Reply::new(Verdict::TimeLimit, "Test timed out.", usage),
+            CodeExec::OutputLimit => Reply::new(Verdict::OutputLimit, "Output limit exceeded.", usage),
+            CodeExec::Executed(Some(0), stdout, stderr) if finished(stderr, nonce) => Reply::new(Verdict::Accepted, &String::from_utf8_lossy(stdout), usage),
+            // The submission exited on its own before the harness got through the tests
+            CodeExec::Executed(Some(0), _, stderr) => {
+                let msg = format!("Exited before all test cases ran.\n{}", error_output(stderr));
+                Reply::new(Verdict::RuntimeError, msg.trim_end(), usage)
+            },
+            CodeExec::Executed(_, _, _) if oom_killed => Reply::new(Verdict::MemoryLimit, "Memory limit exceeded.", usage),
+            CodeExec::Executed(Some(WRONG_ANSWER), stdout, _) => Reply::new(Verdict::WrongAnswer, &String::from_utf8_lossy(stdout), usage),
+            // docker itself failed to run the command, rather than the submission exiting with the same code
+            CodeExec::Executed(Some(125..=127), _, stderr) if docker_failed(stderr) => Reply::internal_error(),
+            CodeExec::Executed(Some(code), _, stderr) if *code > 128 => {
+                let msg = format!("Killed by signal {}.\n{}", signal_name(code - 128), error_output(stderr));
+                Reply::new(Verdict::RuntimeError, msg.trim_end(), usage)
+            },
+            CodeExec::Executed(Some(_), _, stderr) => Reply::new(Verdict::RuntimeError, &error_output(stderr), usage),
+            CodeExec::Executed(None, _, _) => Reply::internal_error(),
+        }
+    }
+}
+
+// Decode stderr for display, leaving out the harness' progress lines
+fn error_output(stderr: &[u8]) -> String {
+    String::from_utf8_lossy(stderr)
+        .split_inclusive('\n')
+        .filter(|l| !l.starts_with(PROGRESS_PREFIX))
+        .collect()
+}
+
+// Whether the harness said it got through every test case
+fn finished(stderr: &[u8], nonce: &str) -> bool {
+    stderr.split(|&b| b == b'\n').any(|l| Progress::parse(l, nonce) == Some(Progress::Done))
+}
+
+fn docker_failed(stderr: &[u8]) -> bool {
+    let output = error_output(stderr);
+    DOCKER_ERRORS.iter().any(|e| output.trim_start().starts_with(e))
+}
+
+fn signal_name(signal: i32) -> String {
+    match signal {
+        4 => "SIGILL".to_string(),
+        6 => "SIGABRT".to_string(),
+        7 => "SIGBUS".to_string(),
+        8 => "SIGFPE".to_string(),
+        9 => "SIGKILL".to_string(),
+        11 => "SIGSEGV".to_string(),
+        s => s.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_exec() {
+        let done = b"#progress abc {\"event\": \"done\"}\n".to_vec();
+        let accepted = Reply::from_exec(&CodeExec::Executed(Some(0), b"All test cases passed!".to_vec(), done.clone()), None, "abc");
+        assert_eq!(accepted, Reply::new(Verdict::Accepted, "All test cases passed!", None));
+        assert!(accepted.success);
+
+        let wrong = Reply::from_exec(&CodeExec::Executed(Some(2), b"Test case failed".to_vec(), Vec::new()), None, "abc");
+        assert_eq!(wrong, Reply::new(Verdict::WrongAnswer, "Test case failed", None));
+        assert!(!wrong.success);
+
+        let error = Reply::from_exec(&CodeExec::Executed(Some(1), Vec::new(), b"#progress {}\nTraceback".to_vec()), None, "abc");
+        assert_eq!(error.verdict, Verdict::RuntimeError);
+        assert_eq!(error.msg, "Traceback");
+
+        let segfault = Reply::from_exec(&CodeExec::Executed(Some(139), Vec::new(), Vec::new()), None, "abc");
+        assert_eq!(segfault, Reply::new(Verdict::RuntimeError, "Killed by signal SIGSEGV.", None));
+
+        let usage = Usage {oom_killed: true, ..Usage::default()};
+        let oom = Reply::from_exec(&CodeExec::Executed(Some(137), Vec::new(), Vec::new()), Some(usage.clone()), "abc");
+        assert_eq!(oom, Reply::new(Verdict::MemoryLimit, "Memory limit exceeded.", Some(usage)));
+
+        assert_eq!(Reply::from_exec(&CodeExec::Timeout, None, "abc").verdict, Verdict::TimeLimit);
+        assert_eq!(Reply::from_exec(&CodeExec::OutputLimit, None, "abc").verdict, Verdict::OutputLimit);
+        let docker = b"docker: Error response from daemon: No such image: test:latest.".to_vec();
+        assert_eq!(Reply::from_exec(&CodeExec::Executed(Some(125), Vec::new(), docker), None, "abc"), Reply::internal_error());
+        let missing = b"OCI runtime exec failed: exec: \"java\": executable file not found in $PATH: unknown".to_vec();
+        assert_eq!(Reply::from_exec(&CodeExec::Executed(Some(126), Vec::new(), missing), None, "abc"), Reply::internal_error());
+
+        // Submissions can exit with the same codes docker uses
+        let exited = Reply::from_exec(&CodeExec::Executed(Some(127), Vec::new(), b"bye".to_vec()), None, "abc");
+        assert_eq!(exited, Reply::new(Verdict::RuntimeError, "bye", None));
+
+        // Exiting cleanly before the harness finishes, or printing its last line without the nonce
+        let early = Reply::from_exec(&CodeExec::Executed(Some(0), Vec::new(), Vec::new()), None, "abc");
+        assert_eq!(early, Reply::new(Verdict::RuntimeError, "Exited before all test cases ran.", None));
+        let forged = b"#progress {\"event\": \"done\"}\n".to_vec();
+        let forged = Reply::from_exec(&CodeExec::Executed(Some(0), b"All test cases passed!".to_vec(), forged), None, "abc");
+        assert_eq!(forged.verdict, Verdict::RuntimeError);
+        let other = Reply::from_exec(&CodeExec::Executed(Some(0), Vec::new(), done), None, "abd");
+        assert_eq!(other.verdict, Verdict::RuntimeError);
+    }
+}
@@ -14,16 +14,30 @@ public class Main {
 
     static {{test_case}};
 
-    public static void main(String[] args) {
+    // Reports progress to the server on stderr, which strips these lines from error messages
+    // The server only believes lines with the nonce it sent on stdin, which is read before the submission runs
+    // It still lives in this JVM, so unlike test.py a determined submission could dig it out of the heap
+    static void progress(String nonce, String event) {
+        System.err.println("#progress " + nonce + " " + event);
+        System.err.flush();
+    }
+
+    public static void main(String[] args) throws java.io.IOException {
+        String nonce = new java.io.BufferedReader(new java.io.InputStreamReader(System.in)).readLine();
+        progress(nonce, "{\"event\": \"compiled\"}");
 
-        for (var t : testCase) {
+        for (int i = 0; i < testCase.length; i++) {
+            var t = testCase[i];
             Solution s = new Solution();
             var output = s.{{func_call}};
-            if (!t.output.equals(output)) {
+            boolean passed = t.output.equals(output);
+            progress(nonce, String.format("{\"event\": \"test\", \"test\": %d, \"total\": %d, \"passed\": %b}", i + 1, testCase.length, passed));
+            if (!passed) {
                 System.out.printf("Test case failed on input %s: Expected\n%s\nbut got\n%s\n", java.util.Arrays.toString(t.input), t.output, output);
                 System.exit(2);
             }
         }
+        progress(nonce, "{\"event\": \"done\"}");
         System.out.println("All test cases passed!");
     }
 }